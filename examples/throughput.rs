@@ -0,0 +1,201 @@
+//! Measures packet throughput of a running server using simulated clients
+//!
+//! Each client joins the same stage and sends player packets at a fixed rate,
+//! while counting the player packets relayed to it from every other client.
+//! Make sure `max_players` in the server config is at least `--clients`.
+//! Every client connects from the same IP, so `max_per_ip` and `accept_burst`
+//! in `[server.connections]` also need to be 0 (the default) or at least `--clients`.
+//!
+//! ```sh
+//! cargo run --release --example throughput -- --clients 32 --rate 60
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, BufMut, BytesMut};
+use clap::Parser;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::TcpStream;
+use uuid::Uuid;
+
+const HEADER_SIZE: usize = 16 + 2 + 2;
+
+const PLAYER_PACKET: u16 = 2;
+const GAME_PACKET: u16 = 4;
+const CONNECT_PACKET: u16 = 6;
+
+#[derive(Debug, Parser)]
+struct Args {
+    /// Server address
+    #[clap(short, long, default_value = "127.0.0.1:1027")]
+    addr: SocketAddr,
+
+    /// Number of simulated clients
+    #[clap(short, long, default_value_t = 16)]
+    clients: usize,
+
+    /// Player packets sent per second by each client
+    #[clap(short, long, default_value_t = 60)]
+    rate: u32,
+
+    /// Duration to send packets for, in seconds
+    #[clap(short, long, default_value_t = 10)]
+    duration: u64,
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    connected: AtomicUsize,
+    sent: AtomicU64,
+    received: AtomicU64,
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    let stats = Arc::new(Stats::default());
+
+    let duration = Duration::from_secs(args.duration);
+    let interval = Duration::from_secs(1) / args.rate.max(1);
+
+    let jobs = (0..args.clients)
+        .map(|idx| tokio::spawn(client(args.addr, idx, interval, duration, stats.clone())))
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    for job in jobs {
+        if let Ok(Err(error)) = job.await {
+            eprintln!("client error: {error}");
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let connected = stats.connected.load(Ordering::Relaxed);
+    let sent = stats.sent.load(Ordering::Relaxed) as f64;
+    let received = stats.received.load(Ordering::Relaxed) as f64;
+
+    let expected = sent * connected.saturating_sub(1) as f64;
+    let delivered = if expected > 0.0 {
+        received / expected * 100.0
+    } else {
+        0.0
+    };
+
+    println!("clients:   {connected}/{} connected", args.clients);
+    println!("sent:      {sent} packets ({:.0}/s)", sent / elapsed);
+    println!(
+        "received:  {received} packets ({:.0}/s)",
+        received / elapsed
+    );
+    println!("delivered: {delivered:.1}% of expected relayed packets");
+
+    Ok(())
+}
+
+async fn client(
+    addr: SocketAddr,
+    idx: usize,
+    interval: Duration,
+    duration: Duration,
+    stats: Arc<Stats>,
+) -> std::io::Result<()> {
+    let stream = TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+
+    let (reader, mut writer) = stream.into_split();
+    let id = Uuid::new_v4();
+
+    let mut buf = BytesMut::new();
+    write_packet(&mut buf, id, CONNECT_PACKET, |buf| {
+        buf.put_u32_le(0);
+        buf.put_u16_le(0);
+        put_fixed_string(buf, &format!("bench{idx}"), 0x20);
+    });
+
+    write_packet(&mut buf, id, GAME_PACKET, |buf| {
+        buf.put_u8(0);
+        buf.put_u8(1);
+        put_fixed_string(buf, "CapWorldHomeStage", 0x40);
+    });
+
+    writer.write_all(&buf).await?;
+    stats.connected.fetch_add(1, Ordering::Relaxed);
+
+    let read_handle = tokio::spawn(read_loop(reader, stats.clone()));
+    let deadline = Instant::now() + duration;
+    let mut ticker = tokio::time::interval(interval);
+
+    let mut x = 0.0f32;
+    while Instant::now() < deadline {
+        ticker.tick().await;
+
+        x += 1.0;
+        buf.clear();
+        write_packet(&mut buf, id, PLAYER_PACKET, |buf| {
+            // Position, rotation, animation weights, act, subact
+            [x, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]
+                .into_iter()
+                .chain([0.0; 6])
+                .for_each(|f| buf.put_f32_le(f));
+
+            buf.put_i16_le(0);
+            buf.put_i16_le(0);
+        });
+
+        writer.write_all(&buf).await?;
+        stats.sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Give the server time to relay any remaining packets
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    read_handle.abort();
+
+    Ok(())
+}
+
+async fn read_loop(mut reader: OwnedReadHalf, stats: Arc<Stats>) -> std::io::Result<()> {
+    let mut buf = BytesMut::with_capacity(64 * 1024);
+
+    loop {
+        if reader.read_buf(&mut buf).await? == 0 {
+            // Server closed the connection, likely because it is full
+            stats.connected.fetch_sub(1, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        while buf.len() >= HEADER_SIZE {
+            let packet_id = u16::from_le_bytes([buf[16], buf[17]]);
+            let body_len = u16::from_le_bytes([buf[18], buf[19]]) as usize;
+
+            if buf.len() < HEADER_SIZE + body_len {
+                break;
+            }
+
+            if packet_id == PLAYER_PACKET {
+                stats.received.fetch_add(1, Ordering::Relaxed);
+            }
+
+            buf.advance(HEADER_SIZE + body_len);
+        }
+    }
+}
+
+fn write_packet(buf: &mut BytesMut, id: Uuid, packet_id: u16, body: impl FnOnce(&mut BytesMut)) {
+    let mut body_buf = BytesMut::new();
+    body(&mut body_buf);
+
+    buf.put(&id.into_bytes()[..]);
+    buf.put_u16_le(packet_id);
+    buf.put_u16_le(body_buf.len() as u16);
+    buf.put(body_buf);
+}
+
+fn put_fixed_string(buf: &mut BytesMut, string: &str, len: usize) {
+    let bytes = string.as_bytes();
+    buf.put(&bytes[..bytes.len().min(len)]);
+    buf.put_bytes(0, len.saturating_sub(bytes.len()));
+}
@@ -1,6 +1,31 @@
 use proc_macro::{self, TokenStream};
 use quote::quote;
-use syn::{parse_macro_input, Data, DataStruct, DeriveInput, Fields, LitStr};
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, Data, DataStruct, DeriveInput, Fields, Ident, LitInt, LitStr, Token};
+
+struct PacketAttr {
+    name: LitStr,
+    padding: usize,
+}
+
+impl Parse for PacketAttr {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let mut padding = 0;
+
+        if input.parse::<Option<Token![,]>>()?.is_some() {
+            let key: Ident = input.parse()?;
+            if key != "padding" {
+                return Err(syn::Error::new(key.span(), "unknown packet option"));
+            }
+
+            input.parse::<Token![=]>()?;
+            padding = input.parse::<LitInt>()?.base10_parse()?;
+        }
+
+        Ok(Self { name, padding })
+    }
+}
 
 pub(crate) fn packet_derive(input: TokenStream) -> TokenStream {
     let DeriveInput {
@@ -11,13 +36,13 @@ pub(crate) fn packet_derive(input: TokenStream) -> TokenStream {
         .iter()
         .filter(|attr| attr.path.is_ident("packet"))
         .map(|attr| {
-            let meta: LitStr = attr.parse_args().unwrap();
+            let meta: PacketAttr = attr.parse_args().unwrap();
             meta
         })
         .next();
 
-    let name = match value {
-        Some(value) => value.parse::<syn::Type>().unwrap(),
+    let (name, padding) = match value {
+        Some(value) => (value.name.parse::<syn::Type>().unwrap(), value.padding),
         None => panic!("missing #[packet()] attribute"),
     };
 
@@ -46,19 +71,34 @@ pub(crate) fn packet_derive(input: TokenStream) -> TokenStream {
         }
     });
 
+    // Trailing padding is always written, but tolerated if missing when reading
+    let pad_writer = quote! {
+        bytes::BufMut::put_bytes(buf, 0, #padding);
+        written += #padding;
+    };
+
+    let pad_reader = quote! {
+        let padding = usize::min(#padding, bytes::Buf::remaining(buf));
+        bytes::Buf::advance(buf, padding);
+    };
+
     let output = quote! {
         #[automatically_derived]
         impl crate::packet::PacketBytes for #ident {
             fn write_bytes(&self, buf: &mut bytes::BytesMut) -> usize {
                 let mut written = 0;
                 #(#writers)*
+                #pad_writer
                 written
             }
 
-            fn from_bytes<T: bytes::Buf>(buf: &mut T) -> color_eyre::Result<Self> {
-                Ok(Self {
+            fn from_bytes<T: bytes::Buf>(buf: &mut T) -> Result<Self, crate::packet::ProtocolError> {
+                let packet = Self {
                     #(#readers)*
-                })
+                };
+
+                #pad_reader
+                Ok(packet)
             }
         }
 
@@ -0,0 +1,528 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use color_eyre::{Report, Result};
+use http_body::{LengthLimitError, Limited};
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use ipnet::IpNet;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::config::{BanTarget, SharedConfig};
+use crate::console::commands::{parse_ip_net, Command, ConfigCommand, MoonCommand};
+use crate::console::handler::handle_command;
+use crate::console::Stage;
+use crate::packet::{ChangeStagePacket, IntoPacket, ProtocolError};
+use crate::player::Costume;
+use crate::server::{Actor, Server};
+
+/// Largest request body read, in bytes
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Serve the admin API if it's enabled, runs the same handlers as the console
+pub async fn listen(server: Arc<Server>, config: SharedConfig) -> Result<()> {
+    let (bind, token) = {
+        let config = config.read().await;
+        if !config.api.enabled {
+            return Ok(());
+        }
+
+        (config.api.bind, config.api.token.clone())
+    };
+
+    if token.is_empty() {
+        warn!("Admin API is enabled without a token, not starting it");
+        return Ok(());
+    }
+
+    let token = Arc::new(token);
+    let make_service = make_service_fn(move |_| {
+        let server = server.clone();
+        let config = config.clone();
+        let token = token.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, server.clone(), config.clone(), token.clone())
+            }))
+        }
+    });
+
+    let builder = hyper::Server::try_bind(&bind)?;
+    info!("Admin API listening on {bind}");
+    builder.serve(make_service).await?;
+
+    Ok(())
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    server: Arc<Server>,
+    config: SharedConfig,
+    token: Arc<String>,
+) -> Result<Response<Body>, Infallible> {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.as_bytes().strip_prefix(b"Bearer "))
+        .is_some_and(|value| bool::from(value.ct_eq(token.as_bytes())));
+
+    let result = if authorized {
+        route(request, server, config).await
+    } else {
+        Err(ApiError::Unauthorized)
+    };
+
+    let response = match result {
+        Ok(value) => json_response(StatusCode::OK, &value),
+        Err(error) => {
+            if let ApiError::Internal(report) = &error {
+                warn!("An error occurred while processing an API request\n{report:?}");
+            }
+
+            json_response(error.status(), &json!({ "error": error.to_string() }))
+        }
+    };
+
+    Ok(response)
+}
+
+async fn route(
+    request: Request<Body>,
+    server: Arc<Server>,
+    config: SharedConfig,
+) -> Result<serde_json::Value, ApiError> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_owned();
+    debug!(%method, path, "api request");
+
+    let command = match (method, path.as_str()) {
+        (Method::GET, "/players") => {
+            let players = list_players(&server);
+            return Ok(json!(players));
+        }
+
+        (Method::POST, "/kick") => {
+            let body: KickRequest = read_json(request).await?;
+            let resolved = server.resolve_players(body.players);
+            let kicked = server.kick(&resolved, body.reason.as_deref(), Actor::Api);
+            if kicked.is_empty() {
+                return Err(ApiError::NotFound);
+            }
+
+            return Ok(json!({ "kicked": kicked }));
+        }
+
+        (Method::POST, "/ban") => {
+            let body: BanRequest = read_json(request).await?;
+            return ban(&server, body).await;
+        }
+
+        (Method::POST, "/unban") => {
+            let body: UnbanRequest = read_json(request).await?;
+            let removed = server.unban(&body.target, Actor::Api).await?;
+            if removed == 0 {
+                return Err(ApiError::NotFound);
+            }
+
+            return Ok(json!({ "removed": removed }));
+        }
+
+        (Method::POST, "/send") => {
+            let body: SendRequest = read_json(request).await?;
+            let resolved = server.resolve_players(body.players.clone());
+            return send_to_stage(&server, &body, resolved);
+        }
+
+        (Method::POST, "/sendall") => {
+            let body: SendRequest = read_json(request).await?;
+            let resolved = server.resolve_players(vec!["*".to_owned()]);
+            return send_to_stage(&server, &body, resolved);
+        }
+
+        (Method::POST, "/moons/add") => {
+            let body: MoonRequest = read_json(request).await?;
+            let resolved = if body.players.is_empty() {
+                None
+            } else {
+                let resolved = server.resolve_players(body.players);
+                if resolved.is_empty() {
+                    return Err(ApiError::NotFound);
+                }
+
+                Some(resolved)
+            };
+
+            let players = server.give_moon(body.id, body.grand, resolved).await?;
+            info!("Added moon {} through the admin API", body.id);
+
+            return Ok(json!({ "players": players }));
+        }
+
+        (Method::POST, "/moons/clear") => Command::Moon(MoonCommand::Clear),
+        (Method::POST, "/moons/sync") => Command::Moon(MoonCommand::Sync),
+        (Method::POST, "/config/reload") => Command::Config(ConfigCommand::Reload),
+
+        (Method::POST, "/shutdown") => {
+            info!("Shutdown requested through the admin API");
+            server.stop();
+
+            return Ok(json!({ "ok": true }));
+        }
+
+        _ => return Err(ApiError::NotFound),
+    };
+
+    handle_command(command, server, config).await?;
+    Ok(json!({ "ok": true }))
+}
+
+/// Ban every target in the request, responds with the bans added and the players kicked
+async fn ban(server: &Server, body: BanRequest) -> Result<serde_json::Value, ApiError> {
+    let mut targets = vec![];
+    if !body.players.is_empty() {
+        let resolved = server.resolve_players(body.players);
+        if resolved.is_empty() {
+            return Err(ApiError::NotFound);
+        }
+
+        targets.extend(resolved.into_iter().map(BanTarget::Id));
+    }
+
+    targets.extend(body.ids.into_iter().map(BanTarget::Id));
+    targets.extend(body.ips.into_iter().map(BanTarget::Ip));
+
+    for pattern in body.names {
+        BanTarget::name_regex(&pattern).map_err(|error| ApiError::BadRequest(error.to_string()))?;
+        targets.push(BanTarget::Name(pattern));
+    }
+
+    if targets.is_empty() {
+        return Err(ApiError::BadRequest("no ban targets".to_owned()));
+    }
+
+    let duration = match body.duration {
+        Some(duration) => Some(
+            humantime::parse_duration(&duration)
+                .map_err(|error| ApiError::BadRequest(error.to_string()))?,
+        ),
+        None => None,
+    };
+
+    let banned = targets.iter().map(ToString::to_string).collect::<Vec<_>>();
+    let kicked = server
+        .ban(targets, body.reason, duration, Actor::Api)
+        .await?;
+
+    Ok(json!({ "banned": banned, "kicked": kicked }))
+}
+
+/// Send the resolved players to a stage, responds with the players sent
+fn send_to_stage(
+    server: &Server,
+    body: &SendRequest,
+    players: HashSet<Uuid>,
+) -> Result<serde_json::Value, ApiError> {
+    let packet = ChangeStagePacket {
+        stage: parse_stage(&body.stage)?.stage_name_fixed(),
+        id: body
+            .warp_id
+            .parse()
+            .map_err(|error: ProtocolError| ApiError::BadRequest(error.to_string()))?,
+        scenario: body.scenario,
+        sub_scenario: 0,
+    };
+
+    if players.is_empty() {
+        return Err(ApiError::NotFound);
+    }
+
+    let sent = players.iter().copied().collect::<Vec<_>>();
+    server.broadcast_some(packet.into_packet(Uuid::nil()), players);
+
+    Ok(json!({ "sent": sent }))
+}
+
+// region: Requests
+#[derive(Debug, Deserialize)]
+struct KickRequest {
+    players: Vec<String>,
+    reason: Option<String>,
+}
+
+/// Any combination of targets can be banned at once
+#[derive(Debug, Deserialize)]
+struct BanRequest {
+    /// Connected players by name or UUID
+    #[serde(default)]
+    players: Vec<String>,
+
+    #[serde(default)]
+    ids: Vec<Uuid>,
+
+    /// Addresses or ranges, eg: 10.0.0.0/8
+    #[serde(default, deserialize_with = "deserialize_ip_nets")]
+    ips: Vec<IpNet>,
+
+    /// Case-insensitive nickname regexes
+    #[serde(default)]
+    names: Vec<String>,
+
+    /// eg: 30m, 12h, 7d [default: permanent]
+    duration: Option<String>,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UnbanRequest {
+    /// UUID, IP range or nickname pattern, as shown in the ban list
+    target: String,
+}
+
+/// Single addresses are accepted as well as ranges, like the `ban ip` command
+fn deserialize_ip_nets<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<IpNet>, D::Error> {
+    Vec::<String>::deserialize(de)?
+        .iter()
+        .map(|addr| parse_ip_net(addr).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct SendRequest {
+    stage: String,
+
+    #[serde(default = "default_scenario")]
+    scenario: i8,
+
+    #[serde(default)]
+    warp_id: String,
+
+    /// Ignored when sending all players
+    #[serde(default)]
+    players: Vec<String>,
+}
+
+#[inline]
+fn default_scenario() -> i8 {
+    -1
+}
+
+#[derive(Debug, Deserialize)]
+struct MoonRequest {
+    id: i32,
+
+    #[serde(default)]
+    grand: bool,
+
+    /// [default: all players]
+    #[serde(default)]
+    players: Vec<String>,
+}
+
+async fn read_json<T: DeserializeOwned>(request: Request<Body>) -> Result<T, ApiError> {
+    // Refuse declared oversized bodies up front, the limit below covers the rest
+    let length = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+
+    if length.is_some_and(|length| length > MAX_BODY_SIZE) {
+        return Err(ApiError::PayloadTooLarge);
+    }
+
+    let body = Limited::new(request.into_body(), MAX_BODY_SIZE);
+    let body = hyper::body::to_bytes(body).await.map_err(|error| {
+        if error.is::<LengthLimitError>() {
+            ApiError::PayloadTooLarge
+        } else {
+            ApiError::BadRequest(error.to_string())
+        }
+    })?;
+
+    serde_json::from_slice(&body).map_err(|error| ApiError::BadRequest(error.to_string()))
+}
+
+#[inline]
+fn parse_stage(stage: &str) -> Result<Stage, ApiError> {
+    stage
+        .parse()
+        .map_err(|error: Report| ApiError::BadRequest(error.to_string()))
+}
+// endregion
+
+// region: Responses
+#[derive(Debug, Serialize)]
+struct PlayerInfo {
+    id: Uuid,
+    name: String,
+    stage: Option<String>,
+    scenario: Option<u8>,
+    costume: Option<Costume>,
+    position: Option<[f32; 3]>,
+
+    /// Seconds since the player joined
+    connected: u64,
+}
+
+fn list_players(server: &Server) -> Vec<PlayerInfo> {
+    let mut players = server.map_players(|player| PlayerInfo {
+        id: player.id,
+        name: player.name.clone(),
+        stage: player.stage().map(ToOwned::to_owned),
+        scenario: player.last_game.map(|game| game.scenario),
+        costume: player.costume.clone(),
+        position: player.last_pos.map(|pos| pos.position.to_array()),
+        connected: player.connected_at.elapsed().as_secs(),
+    });
+
+    players.sort_by(|a, b| a.name.cmp(&b.name));
+    players
+}
+
+fn json_response(status: StatusCode, value: &serde_json::Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(value.to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+
+    response
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ApiError {
+    #[error("missing or invalid token")]
+    Unauthorized,
+
+    #[error("not found")]
+    NotFound,
+
+    #[error("{0}")]
+    BadRequest(String),
+
+    #[error("request body is too large")]
+    PayloadTooLarge,
+
+    #[error("internal server error")]
+    Internal(#[from] Report),
+}
+
+impl ApiError {
+    #[inline]
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::Args;
+
+    const TOKEN: &str = "secret";
+
+    async fn server() -> (Arc<Server>, SharedConfig) {
+        let mut config = Config::default();
+        config.moons.persist = false;
+        config.profiles.persist = false;
+
+        let config = Arc::new(RwLock::new(config));
+        let args = Args::parse_from(["test"]);
+        let server = Server::new(&args, config.clone()).await.unwrap();
+
+        (server, config)
+    }
+
+    fn post(path: &str, token: Option<&str>, body: impl Into<Body>) -> Request<Body> {
+        let mut builder = Request::builder().method(Method::POST).uri(path);
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        builder.body(body.into()).unwrap()
+    }
+
+    async fn status(request: Request<Body>) -> StatusCode {
+        let (server, config) = server().await;
+        let token = Arc::new(TOKEN.to_owned());
+
+        let response = handle_request(request, server, config, token)
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn test_missing_token() {
+        let request = post("/moons/sync", None, "");
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_token() {
+        let request = post("/moons/sync", Some("wrong"), "");
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+
+        // The token without the Bearer scheme
+        let mut request = post("/moons/sync", None, "");
+        let header = TOKEN.parse().unwrap();
+        request.headers_mut().insert(AUTHORIZATION, header);
+        assert_eq!(status(request).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route() {
+        let request = post("/unknown", Some(TOKEN), "");
+        assert_eq!(status(request).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_bad_body() {
+        let request = post("/kick", Some(TOKEN), "not json");
+        assert_eq!(status(request).await, StatusCode::BAD_REQUEST);
+
+        let request = post("/kick", Some(TOKEN), r#"{"reason": "missing players"}"#);
+        assert_eq!(status(request).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_body_too_large() {
+        let body = format!(r#"{{"players": ["{}"]}}"#, "a".repeat(MAX_BODY_SIZE));
+        let request = post("/kick", Some(TOKEN), body);
+        assert_eq!(status(request).await, StatusCode::PAYLOAD_TOO_LARGE);
+
+        // A declared length over the limit is refused before reading
+        let mut request = post("/kick", Some(TOKEN), "{}");
+        let length = (MAX_BODY_SIZE + 1).into();
+        request.headers_mut().insert(CONTENT_LENGTH, length);
+        assert_eq!(status(request).await, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_no_players_found() {
+        let request = post("/kick", Some(TOKEN), r#"{"players": ["*"]}"#);
+        assert_eq!(status(request).await, StatusCode::NOT_FOUND);
+
+        let body = r#"{"stage": "cap", "players": ["Mario"]}"#;
+        let request = post("/send", Some(TOKEN), body);
+        assert_eq!(status(request).await, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,25 +1,47 @@
-use std::collections::HashSet;
-use std::net::IpAddr;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU8;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::Context;
 use color_eyre::Result;
+use ipnet::IpNet;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tokio::sync::RwLock;
+use tracing::warn;
 use uuid::Uuid;
 
+use crate::moons::Moon;
+use crate::packet::FrameMode;
+use crate::player::Player;
+
 pub type SharedConfig = Arc<RwLock<Config>>;
 
 // region: Config
-#[derive(Debug, Default, Deserialize, Serialize)]
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub bans: BanConfig,
     pub moons: MoonConfig,
     pub costumes: CostumeConfig,
+
+    #[serde(default)]
+    pub profiles: ProfileConfig,
+
+    #[serde(default)]
+    pub whitelist: WhitelistConfig,
+
+    #[serde(default)]
+    pub names: NameConfig,
+
+    #[serde(default)]
+    pub api: ApiConfig,
 }
 
 impl Config {
@@ -35,8 +57,7 @@ impl Config {
             return Ok(config);
         }
 
-        let bytes = fs::read(&path).await.context("failed to read config")?;
-        if let Ok(config) = toml::from_slice::<Config>(&bytes) {
+        if let Ok(config) = Self::read().await {
             Ok(config)
         } else {
             let config = Self::load_default().await?;
@@ -44,18 +65,27 @@ impl Config {
         }
     }
 
-    async fn load_default() -> Result<Self> {
-        let config = Self::default();
-        config.save().await?;
+    /// Read the config file, without replacing it with the default config if it's invalid
+    pub async fn read() -> Result<Self> {
+        let bytes = fs::read(Self::path_buf())
+            .await
+            .context("failed to read config")?;
 
+        let config = toml::from_slice(&bytes).context("failed to parse config")?;
         Ok(config)
     }
 
-    pub async fn reload(&mut self) -> Result<()> {
-        let config = Self::load().await?;
-        *self = config;
+    /// Last time the config file was modified
+    pub async fn modified() -> Option<SystemTime> {
+        let metadata = fs::metadata(Self::path_buf()).await.ok()?;
+        metadata.modified().ok()
+    }
 
-        Ok(())
+    async fn load_default() -> Result<Self> {
+        let config = Self::default();
+        config.save().await?;
+
+        Ok(config)
     }
 
     pub async fn save(&self) -> Result<()> {
@@ -77,11 +107,44 @@ impl Config {
 // endregion
 
 // region: ServerConfig
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct ServerConfig {
     host: Option<IpAddr>,
     port: Option<u16>,
     max_players: NonZeroU8,
+
+    #[serde(default)]
+    routing: Routing,
+
+    /// Unknown packet ids that are relayed to other players unchanged
+    #[serde(default)]
+    relayed_raw_ids: BTreeSet<u16>,
+
+    #[serde(default)]
+    frame_mode: FrameMode,
+
+    /// Port of the optional UDP socket used for movement packets
+    #[serde(default)]
+    udp_port: Option<u16>,
+
+    /// Shared secret players need to join, sent as a `name#password` nickname
+    /// or after the connect packet as `PASS`, a length byte and the password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+
+    /// Seconds a disconnected player's state is kept for them to reconnect
+    #[serde(default = "default_reconnect_grace")]
+    reconnect_grace: u64,
+
+    // Tables have to be serialized after plain values
+    #[serde(default)]
+    send_queue: SendQueueConfig,
+
+    #[serde(default)]
+    connections: ConnectionConfig,
+
+    #[serde(default)]
+    flood: FloodConfig,
 }
 
 impl Default for ServerConfig {
@@ -91,10 +154,160 @@ impl Default for ServerConfig {
             host: None,
             port: None,
             max_players: NonZeroU8::new(8).unwrap(),
+            routing: Routing::default(),
+            relayed_raw_ids: BTreeSet::new(),
+            frame_mode: FrameMode::default(),
+            udp_port: None,
+            password: None,
+            reconnect_grace: default_reconnect_grace(),
+            send_queue: SendQueueConfig::default(),
+            connections: ConnectionConfig::default(),
+            flood: FloodConfig::default(),
+        }
+    }
+}
+
+#[inline]
+fn default_reconnect_grace() -> u64 {
+    60
+}
+
+/// How movement packets (player, cap and capture) are sent to other players
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Routing {
+    /// Send to every other player
+    #[default]
+    Global,
+
+    /// Only send to players in the same stage
+    Stage,
+}
+
+/// Limits for packets waiting to be sent to each player
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SendQueueConfig {
+    /// Queue length at which stale player packets are dropped
+    pub coalesce_len: usize,
+
+    /// Queue length at which the player is disconnected
+    pub max_len: usize,
+}
+
+impl Default for SendQueueConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            coalesce_len: 64,
+            max_len: 512,
+        }
+    }
+}
+
+/// Limits for new connections, applied before the player joins
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ConnectionConfig {
+    /// Open connections allowed from the same IP, 0 for no limit
+    pub max_per_ip: usize,
+
+    /// Connections accepted from the same IP in a burst, 0 for no limit
+    pub accept_burst: u32,
+
+    /// Connections accepted from the same IP every second after a burst
+    pub accept_rate: f64,
+
+    /// Seconds a client has to send its connect packet
+    pub handshake_timeout: u64,
+}
+
+impl Default for ConnectionConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            max_per_ip: 0,
+            accept_burst: 0,
+            accept_rate: 1.0,
+            handshake_timeout: 10,
         }
     }
 }
 
+impl ConnectionConfig {
+    #[inline]
+    pub fn max_per_ip(&self) -> usize {
+        match self.max_per_ip {
+            0 => usize::MAX,
+            max => max,
+        }
+    }
+
+    #[inline]
+    pub fn handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.handshake_timeout)
+    }
+}
+
+/// Limits for packets sent by each player
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FloodConfig {
+    pub enabled: bool,
+
+    /// Packets dropped before the player is disconnected, one is forgiven every second
+    pub max_dropped: u32,
+
+    /// Minutes players disconnected for flooding are banned for, 0 to only disconnect them
+    pub ban_minutes: u64,
+
+    /// Limits by packet type, eg: `player`, `cap` or `raw`. Other types aren't limited
+    pub limits: BTreeMap<String, PacketLimit>,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        let limits = [
+            ("player", 120, 60.0),
+            ("cap", 120, 60.0),
+            ("game", 10, 2.0),
+            ("tag", 10, 2.0),
+            ("costume", 5, 1.0),
+            // Clients send every collected moon when joining
+            ("moon", 1000, 20.0),
+            ("capture", 10, 2.0),
+            ("change_stage", 10, 2.0),
+            ("raw", 60, 30.0),
+        ];
+
+        Self {
+            enabled: true,
+            max_dropped: 100,
+            ban_minutes: 0,
+            limits: limits
+                .into_iter()
+                .map(|(name, burst, rate)| (name.to_owned(), PacketLimit { burst, rate }))
+                .collect(),
+        }
+    }
+}
+
+impl FloodConfig {
+    #[inline]
+    pub fn ban_duration(&self) -> Option<Duration> {
+        (self.ban_minutes > 0).then(|| Duration::from_secs(self.ban_minutes * 60))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct PacketLimit {
+    /// Packets allowed in a burst
+    pub burst: u32,
+
+    /// Packets allowed every second after a burst
+    pub rate: f64,
+}
+
 impl ServerConfig {
     #[inline]
     pub fn host(&self) -> Option<IpAddr> {
@@ -110,14 +323,65 @@ impl ServerConfig {
     pub fn max_players(&self) -> u16 {
         u16::from(self.max_players.get())
     }
+
+    #[inline]
+    pub fn routing(&self) -> Routing {
+        self.routing
+    }
+
+    #[inline]
+    pub fn relays_raw(&self, packet_id: u16) -> bool {
+        self.relayed_raw_ids.contains(&packet_id)
+    }
+
+    #[inline]
+    pub fn send_queue(&self) -> SendQueueConfig {
+        self.send_queue
+    }
+
+    #[inline]
+    pub fn connections(&self) -> ConnectionConfig {
+        self.connections
+    }
+
+    #[inline]
+    pub fn flood(&self) -> &FloodConfig {
+        &self.flood
+    }
+
+    #[inline]
+    pub fn frame_mode(&self) -> FrameMode {
+        self.frame_mode
+    }
+
+    #[inline]
+    pub fn udp_port(&self) -> Option<u16> {
+        self.udp_port
+    }
+
+    #[inline]
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+
+    #[inline]
+    pub fn reconnect_grace(&self) -> Duration {
+        Duration::from_secs(self.reconnect_grace)
+    }
 }
 // endregion
 
 // region: BanConfig
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct BanConfig {
     pub enabled: bool,
+
+    /// Permanently banned UUIDs
     pub banned_ids: HashSet<Uuid>,
+
+    /// Bans by UUID, IP range or nickname, with an optional reason and expiry
+    #[serde(default)]
+    pub entries: Vec<Ban>,
 }
 
 impl Default for BanConfig {
@@ -126,16 +390,157 @@ impl Default for BanConfig {
         Self {
             enabled: true,
             banned_ids: HashSet::new(),
+            entries: vec![],
+        }
+    }
+}
+
+impl BanConfig {
+    /// First active ban matching a player, regardless of whether bans are enabled
+    pub fn find(&self, id: &Uuid, ip: IpAddr, name: &str) -> Option<Ban> {
+        if self.banned_ids.contains(id) {
+            return Some(Ban::new(BanTarget::Id(*id)));
+        }
+
+        self.active()
+            .find(|ban| ban.target.matches(id, ip, name))
+            .cloned()
+    }
+
+    #[inline]
+    pub fn active(&self) -> impl Iterator<Item = &Ban> + '_ {
+        self.entries.iter().filter(|ban| !ban.is_expired())
+    }
+
+    /// Add a ban, replacing any existing ban with the same target
+    pub fn add(&mut self, ban: Ban) {
+        self.entries
+            .retain(|entry| entry.target != ban.target && !entry.is_expired());
+
+        self.entries.push(ban);
+    }
+
+    /// Remove bans whose target is written as `target`, returns the number removed
+    ///
+    /// Expired bans are pruned as well, but aren't counted.
+    pub fn remove(&mut self, target: &str) -> usize {
+        self.entries.retain(|ban| !ban.is_expired());
+        let len = self.banned_ids.len() + self.entries.len();
+
+        self.banned_ids.retain(|id| id.to_string() != target);
+        self.entries.retain(|ban| ban.target.value() != target);
+
+        len - self.banned_ids.len() - self.entries.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Ban {
+    #[serde(flatten)]
+    pub target: BanTarget,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// Permanent if `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Ban {
+    #[inline]
+    pub fn new(target: BanTarget) -> Self {
+        Self {
+            target,
+            reason: None,
+            expires: None,
+        }
+    }
+
+    #[inline]
+    pub fn is_expired(&self) -> bool {
+        matches!(self.expires, Some(expires) if expires <= Utc::now())
+    }
+}
+
+impl Display for Ban {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.target)?;
+
+        match &self.expires {
+            Some(expires) => write!(f, " until {}", expires.format("%Y-%m-%d %H:%M:%S UTC"))?,
+            None => write!(f, " permanently")?,
+        }
+
+        if let Some(reason) = &self.reason {
+            write!(f, ": {reason}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BanTarget {
+    Id(Uuid),
+    Ip(IpNet),
+
+    /// Case-insensitive regex matched against the whole nickname
+    Name(String),
+}
+
+impl BanTarget {
+    pub fn matches(&self, id: &Uuid, ip: IpAddr, name: &str) -> bool {
+        match self {
+            BanTarget::Id(banned) => banned == id,
+            BanTarget::Ip(net) => net.contains(&ip),
+            BanTarget::Name(pattern) => match Self::name_regex(pattern) {
+                Ok(regex) => regex.is_match(name),
+                Err(error) => {
+                    warn!(%pattern, %error, "invalid nickname ban");
+                    false
+                }
+            },
+        }
+    }
+
+    /// Compile a nickname pattern the same way it is matched against players
+    #[inline]
+    pub fn name_regex(pattern: &str) -> Result<Regex, regex::Error> {
+        Regex::new(&format!("(?i)^(?:{pattern})$"))
+    }
+
+    /// Target without its kind, as used by `unban`
+    #[inline]
+    pub fn value(&self) -> String {
+        match self {
+            BanTarget::Id(id) => id.to_string(),
+            BanTarget::Ip(net) => net.to_string(),
+            BanTarget::Name(pattern) => pattern.clone(),
+        }
+    }
+}
+
+impl Display for BanTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BanTarget::Id(id) => write!(f, "id {id}"),
+            BanTarget::Ip(net) => write!(f, "ip {net}"),
+            BanTarget::Name(pattern) => write!(f, "name {pattern}"),
         }
     }
 }
 // endregion
 
 // region: MoonConfig
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct MoonConfig {
     pub persist: bool,
     pub persist_file: PathBuf,
+
+    #[serde(default)]
+    pub sync: MoonSyncConfig,
 }
 
 impl Default for MoonConfig {
@@ -144,13 +549,41 @@ impl Default for MoonConfig {
         Self {
             persist: true,
             persist_file: PathBuf::from("./moons.toml"),
+            sync: MoonSyncConfig::default(),
         }
     }
 }
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MoonSyncConfig {
+    /// Moons that are never synced to other players
+    pub denied_ids: HashSet<i32>,
+
+    /// Only sync moons collected in kingdoms the player has already visited
+    pub visited_kingdoms_only: bool,
+}
+
+impl MoonSyncConfig {
+    pub fn should_sync(&self, moon: &Moon, player: &Player) -> bool {
+        if self.denied_ids.contains(&moon.id) {
+            return false;
+        }
+
+        if self.visited_kingdoms_only {
+            // Moons without a known kingdom are always synced
+            if let Some(kingdom) = &moon.kingdom {
+                return player.kingdoms.contains(kingdom);
+            }
+        }
+
+        true
+    }
+}
 // endregion
 
 // region: CostumesConfig
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct CostumeConfig {
     pub banned_costumes: HashSet<String>,
     pub allowed_players: HashSet<Uuid>,
@@ -178,3 +611,276 @@ impl CostumeConfig {
     }
 }
 // endregion
+
+// region: ProfileConfig
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub persist: bool,
+    pub persist_file: PathBuf,
+}
+
+impl Default for ProfileConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            persist: true,
+            persist_file: PathBuf::from("./profiles.toml"),
+        }
+    }
+}
+// endregion
+
+// region: WhitelistConfig
+/// Only allow listed players to join while enabled
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WhitelistConfig {
+    pub enabled: bool,
+    pub ids: HashSet<Uuid>,
+}
+
+impl WhitelistConfig {
+    #[inline]
+    pub fn is_allowed(&self, id: &Uuid) -> bool {
+        !self.enabled || self.ids.contains(id)
+    }
+}
+// endregion
+
+// region: NameConfig
+/// Rules for player nicknames
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NameConfig {
+    /// Regex the whole nickname has to match, after control characters and
+    /// surrounding whitespace are removed
+    pub pattern: String,
+
+    /// Case-insensitive words that can't be part of a nickname
+    pub blocked_words: Vec<String>,
+
+    pub duplicates: DuplicateNames,
+}
+
+impl Default for NameConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            pattern: r"[^\p{C}]+".to_owned(),
+            blocked_words: vec![],
+            duplicates: DuplicateNames::default(),
+        }
+    }
+}
+
+/// What to do when a player joins with the nickname of a connected player
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateNames {
+    Reject,
+
+    /// Add a number to the end of the nickname, eg: `Mario_2`
+    #[default]
+    Suffix,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum NameError {
+    #[error("nickname doesn't match the allowed pattern")]
+    Pattern,
+
+    #[error("nickname contains the blocked word {0:?}")]
+    BlockedWord(String),
+
+    #[error("nickname is already used by a connected player")]
+    Duplicate,
+
+    #[error("invalid nickname pattern in config: {0}")]
+    InvalidPattern(#[from] regex::Error),
+}
+
+impl NameConfig {
+    /// Remove control characters and surrounding whitespace, then check the
+    /// nickname against the pattern and blocked words
+    pub fn sanitize(&self, name: &str) -> Result<String, NameError> {
+        let name = name
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect::<String>()
+            .trim()
+            .to_owned();
+
+        let pattern = Regex::new(&format!("^(?:{})$", self.pattern))?;
+        if !pattern.is_match(&name) {
+            return Err(NameError::Pattern);
+        }
+
+        let lowercase = name.to_lowercase();
+        if let Some(word) = self
+            .blocked_words
+            .iter()
+            .find(|word| lowercase.contains(&word.to_lowercase()))
+        {
+            return Err(NameError::BlockedWord(word.clone()));
+        }
+
+        Ok(name)
+    }
+}
+// endregion
+
+// region: ApiConfig
+/// Local HTTP admin API, requests need an `Authorization: Bearer <token>` header
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub enabled: bool,
+    pub bind: SocketAddr,
+
+    /// The API isn't started without a token
+    pub token: String,
+}
+
+impl Default for ApiConfig {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind: SocketAddr::from(([127, 0, 0, 1], 1028)),
+            token: String::new(),
+        }
+    }
+}
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn expired(target: BanTarget) -> Ban {
+        Ban {
+            expires: Some(Utc::now() - chrono::Duration::minutes(1)),
+            ..Ban::new(target)
+        }
+    }
+
+    #[test]
+    fn test_ban_ip_range() {
+        let id = Uuid::new_v4();
+        let target = BanTarget::Ip("10.0.0.0/8".parse().unwrap());
+        assert!(target.matches(&id, ip("10.1.2.3"), "Mario"));
+        assert!(!target.matches(&id, ip("11.0.0.1"), "Mario"));
+
+        let target = BanTarget::Ip("192.168.1.7/32".parse().unwrap());
+        assert!(target.matches(&id, ip("192.168.1.7"), "Mario"));
+        assert!(!target.matches(&id, ip("192.168.1.8"), "Mario"));
+
+        let target = BanTarget::Ip("fd00::/8".parse().unwrap());
+        assert!(target.matches(&id, ip("fd12::1"), "Mario"));
+        assert!(!target.matches(&id, ip("10.1.2.3"), "Mario"));
+    }
+
+    #[test]
+    fn test_ban_name() {
+        let (id, addr) = (Uuid::new_v4(), ip("127.0.0.1"));
+        let target = BanTarget::Name("bad.*".into());
+        assert!(target.matches(&id, addr, "BadGuy"));
+        assert!(target.matches(&id, addr, "bad"));
+
+        // The whole nickname has to match
+        assert!(!target.matches(&id, addr, "NotBad"));
+
+        // Alternations are still anchored on both sides
+        let target = BanTarget::Name("foo|bar".into());
+        assert!(target.matches(&id, addr, "FOO"));
+        assert!(!target.matches(&id, addr, "foobar"));
+
+        // Invalid patterns never match
+        let target = BanTarget::Name("(".into());
+        assert!(!target.matches(&id, addr, "("));
+    }
+
+    #[test]
+    fn test_ban_expiry() {
+        let (id, addr) = (Uuid::new_v4(), ip("127.0.0.1"));
+        let mut bans = BanConfig::default();
+        bans.entries.push(expired(BanTarget::Id(id)));
+        assert!(bans.find(&id, addr, "Mario").is_none());
+
+        let ban = Ban {
+            expires: Some(Utc::now() + chrono::Duration::minutes(1)),
+            ..Ban::new(BanTarget::Id(id))
+        };
+        bans.add(ban);
+        assert!(bans.find(&id, addr, "Mario").is_some());
+        assert_eq!(bans.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_ban_remove() {
+        let id = Uuid::new_v4();
+        let mut bans = BanConfig::default();
+        bans.banned_ids.insert(id);
+        bans.entries.push(Ban::new(BanTarget::Id(id)));
+        bans.entries.push(Ban::new(BanTarget::Name("Luigi".into())));
+        bans.entries.push(expired(BanTarget::Name("Wario".into())));
+
+        assert_eq!(bans.remove("10.0.0.0/8"), 0);
+        assert_eq!(bans.entries.len(), 2);
+
+        assert_eq!(bans.remove(&id.to_string()), 2);
+        assert_eq!(bans.remove("Luigi"), 1);
+        assert!(bans.banned_ids.is_empty() && bans.entries.is_empty());
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        let names = NameConfig::default();
+        assert_eq!(names.sanitize("Mario").unwrap(), "Mario");
+        assert_eq!(names.sanitize("  Ma\u{7}ri\no\t ").unwrap(), "Mario");
+        assert_eq!(names.sanitize("Super Mario").unwrap(), "Super Mario");
+        assert_eq!(names.sanitize("マリオ").unwrap(), "マリオ");
+    }
+
+    #[test]
+    fn test_sanitize_empty_name() {
+        let names = NameConfig::default();
+        assert!(matches!(names.sanitize(""), Err(NameError::Pattern)));
+        assert!(matches!(names.sanitize("   "), Err(NameError::Pattern)));
+        assert!(matches!(
+            names.sanitize("\0\u{1b}\r\n"),
+            Err(NameError::Pattern)
+        ));
+    }
+
+    #[test]
+    fn test_sanitize_rules() {
+        let names = NameConfig {
+            pattern: "[a-z0-9_]{3,16}".to_owned(),
+            blocked_words: vec!["Bowser".to_owned()],
+            ..NameConfig::default()
+        };
+
+        assert!(names.sanitize("mario_64").is_ok());
+        assert!(matches!(names.sanitize("mr"), Err(NameError::Pattern)));
+        assert!(matches!(names.sanitize("mario!"), Err(NameError::Pattern)));
+        assert!(matches!(
+            names.sanitize("mbowserx"),
+            Err(NameError::BlockedWord(word)) if word == "Bowser"
+        ));
+
+        let names = NameConfig {
+            pattern: "(".to_owned(),
+            ..NameConfig::default()
+        };
+        assert!(matches!(
+            names.sanitize("mario"),
+            Err(NameError::InvalidPattern(_))
+        ));
+    }
+}
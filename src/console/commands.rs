@@ -1,4 +1,9 @@
-use clap::Parser;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use clap::{Args, Parser};
+use ipnet::IpNet;
+use uuid::Uuid;
 
 use super::Stage;
 
@@ -9,15 +14,43 @@ use super::Stage;
     no_binary_name = true
 )]
 pub enum Command {
+    #[clap(subcommand)]
+    Ban(BanCommand),
+
+    /// List all active bans
+    #[clap(name = "banlist")]
+    BanList,
+
     #[clap(subcommand)]
     Config(ConfigCommand),
 
+    /// Crash player(s) back to the title screen and disconnect them
+    Crash {
+        players: Vec<String>,
+
+        /// Reason to log, the rest of the line is used
+        #[clap(short, long, num_args = 1..)]
+        reason: Vec<String>,
+    },
+
+    /// Disconnect player(s)
+    Kick {
+        players: Vec<String>,
+
+        /// Reason to log, the rest of the line is used
+        #[clap(short, long, num_args = 1..)]
+        reason: Vec<String>,
+    },
+
     /// List all currently connected players
     List,
 
     #[clap(subcommand)]
     Moon(MoonCommand),
 
+    #[clap(subcommand)]
+    Profile(ProfileCommand),
+
     /// Send player(s) to a stage
     #[clap(allow_negative_numbers = true)]
     Send {
@@ -37,11 +70,74 @@ pub enum Command {
         warp_id: String,
     },
 
+    #[clap(subcommand)]
+    Tag(TagCommand),
+
+    /// Remove bans for a UUID, IP range or nickname pattern
+    Unban { target: String },
+
+    #[clap(subcommand)]
+    Whitelist(WhitelistCommand),
+
     /// Stop the server and exit
     #[clap(alias = "quit", alias = "stop", alias = "q")]
     Exit,
 }
 
+#[derive(Debug, Parser)]
+pub enum BanCommand {
+    /// Ban connected player(s) by UUID
+    Player {
+        players: Vec<String>,
+
+        #[clap(flatten)]
+        options: BanOptions,
+    },
+
+    /// Ban a UUID, the player doesn't have to be connected
+    Id {
+        id: Uuid,
+
+        #[clap(flatten)]
+        options: BanOptions,
+    },
+
+    /// Ban an IP address or range, eg: 10.0.0.0/8
+    Ip {
+        #[clap(value_parser = parse_ip_net)]
+        addr: IpNet,
+
+        #[clap(flatten)]
+        options: BanOptions,
+    },
+
+    /// Ban nicknames matching a case-insensitive regex
+    Name {
+        pattern: String,
+
+        #[clap(flatten)]
+        options: BanOptions,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct BanOptions {
+    /// How long the ban lasts, eg: 30m, 12h, 7d [default: permanent]
+    #[clap(short, long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+
+    /// Reason for the ban, the rest of the line is used
+    #[clap(short, long, num_args = 1..)]
+    pub reason: Vec<String>,
+}
+
+pub fn parse_ip_net(value: &str) -> Result<IpNet, String> {
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("invalid IP address or range: {value}"))
+}
+
 #[derive(Debug, Parser)]
 pub enum ConfigCommand {
     /// Reload config from file
@@ -62,10 +158,68 @@ pub enum MoonCommand {
     /// Reload moons from moon file (if persistence is enabled)
     Reload,
 
+    /// Clear all collected moons
     Clear,
 
-    /// Manually add a specific moon to
+    /// Manually add a specific moon and send it to player(s)
     Add {
         id: i32,
+
+        /// Send the moon as a grand moon (multi-moon)
+        #[clap(short, long)]
+        grand: bool,
+
+        /// Players to send the moon to [default: all players]
+        players: Vec<String>,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub enum ProfileCommand {
+    /// List all known players, most recently seen first
+    List,
+
+    /// Show everything known about a player
+    Show {
+        /// Nickname or UUID, including players that are offline
+        player: String,
     },
 }
+
+#[derive(Debug, Parser)]
+pub enum WhitelistCommand {
+    /// Allow player(s) to join, by UUID or name if they are connected
+    Add { players: Vec<String> },
+
+    /// Stop allowing player(s) to join, by UUID or name if they are connected
+    Remove { players: Vec<String> },
+
+    /// List all whitelisted players
+    List,
+
+    /// Only allow whitelisted players to join
+    On,
+
+    /// Allow anyone to join
+    Off,
+}
+
+#[derive(Debug, Parser)]
+pub enum TagCommand {
+    /// Start a round of hide and seek
+    Start,
+
+    /// Set the seeker(s), everyone else will be hiding
+    Seeker { players: Vec<String> },
+
+    /// Set the round time, a running round keeps its current countdown
+    Time {
+        minutes: u16,
+
+        #[clap(value_parser = clap::value_parser!(u8).range(0..=59))]
+        seconds: u8,
+    },
+
+    /// Stop the current round
+    Stop,
+}
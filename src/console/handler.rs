@@ -1,16 +1,21 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write as _;
 use std::sync::Arc;
+use std::time::Duration;
 
-use color_eyre::eyre::bail;
 use color_eyre::Result;
 use tracing::{info, warn};
 use uuid::Uuid;
 
-use super::commands::{Command, ConfigCommand, MoonCommand};
-use crate::config::SharedConfig;
+use super::commands::{
+    BanCommand, Command, ConfigCommand, MoonCommand, ProfileCommand, TagCommand, WhitelistCommand,
+};
+use crate::config::{Ban, BanTarget, SharedConfig};
+use crate::moons::Moon;
 use crate::packet::{ChangeStagePacket, IntoPacket};
-use crate::server::Server;
+use crate::server::{Actor, Server};
 
-pub(super) async fn handle_command(
+pub async fn handle_command(
     command: Command,
     server: Arc<Server>,
     config: SharedConfig,
@@ -19,11 +24,7 @@ pub(super) async fn handle_command(
         Command::Exit => Ok(HandleResult::Exit),
 
         Command::Config(ConfigCommand::Reload) => {
-            let mut config = config.write().await;
-
-            config.reload().await?;
-            info!("Loaded config from file");
-
+            server.reload_config().await?;
             Ok(HandleResult::Ok)
         }
 
@@ -36,8 +37,109 @@ pub(super) async fn handle_command(
             Ok(HandleResult::Ok)
         }
 
+        Command::Ban(command) => {
+            let (targets, options) = match command {
+                BanCommand::Player { players, options } => {
+                    let resolved = server.resolve_players(players);
+                    if resolved.is_empty() {
+                        warn!("No players selected! (Use * to select all players)");
+                        return Ok(HandleResult::Ok);
+                    }
+
+                    let targets = resolved.into_iter().map(BanTarget::Id).collect();
+                    (targets, options)
+                }
+
+                BanCommand::Id { id, options } => (vec![BanTarget::Id(id)], options),
+                BanCommand::Ip { addr, options } => (vec![BanTarget::Ip(addr)], options),
+
+                BanCommand::Name { pattern, options } => {
+                    if let Err(error) = BanTarget::name_regex(&pattern) {
+                        warn!("Invalid nickname pattern: {error}");
+                        return Ok(HandleResult::Ok);
+                    }
+
+                    (vec![BanTarget::Name(pattern)], options)
+                }
+            };
+
+            let reason = (!options.reason.is_empty()).then(|| options.reason.join(" "));
+            server
+                .ban(targets, reason, options.duration, Actor::Console)
+                .await?;
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Unban { target } => {
+            let removed = server.unban(&target, Actor::Console).await?;
+
+            if removed == 0 {
+                warn!("No bans found for {target}");
+            }
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::BanList => {
+            let config = config.read().await;
+
+            let bans = config
+                .bans
+                .banned_ids
+                .iter()
+                .map(|id| Ban::new(BanTarget::Id(*id)))
+                .chain(config.bans.active().cloned())
+                .collect::<Vec<_>>();
+
+            if bans.is_empty() {
+                info!("No players are banned");
+                return Ok(HandleResult::Ok);
+            }
+
+            let mut output = format!("{} bans", bans.len());
+            if !config.bans.enabled {
+                output.push_str(" (disabled)");
+            }
+
+            for ban in bans {
+                let _ = write!(output, "\n  {ban}");
+            }
+
+            info!("{output}");
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Kick { players, reason } => {
+            let resolved = server.resolve_players(players);
+            if resolved.is_empty() {
+                warn!("No players selected! (Use * to select all players)");
+                return Ok(HandleResult::Ok);
+            }
+
+            let reason = (!reason.is_empty()).then(|| reason.join(" "));
+            let kicked = server.kick(&resolved, reason.as_deref(), Actor::Console);
+            info!("Kicked {} player(s)", kicked.len());
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Crash { players, reason } => {
+            let resolved = server.resolve_players(players);
+            if resolved.is_empty() {
+                warn!("No players selected! (Use * to select all players)");
+                return Ok(HandleResult::Ok);
+            }
+
+            let reason = (!reason.is_empty()).then(|| reason.join(" "));
+            let crashed = server.crash(&resolved, reason.as_deref(), Actor::Console)?;
+            info!("Crashed {crashed} player(s)");
+
+            Ok(HandleResult::Ok)
+        }
+
         Command::List => {
-            let players = server.list_players().await;
+            let players = server.list_players();
             info!(?players);
 
             Ok(HandleResult::Ok)
@@ -49,7 +151,7 @@ pub(super) async fn handle_command(
             warp_id,
             players,
         } => {
-            let resolved = server.resolve_players(players).await;
+            let resolved = server.resolve_players(players);
             if resolved.is_empty() {
                 warn!("No players selected! (Use * to select all players)");
                 return Ok(HandleResult::Ok);
@@ -63,7 +165,7 @@ pub(super) async fn handle_command(
             };
 
             let packet = packet.into_packet(Uuid::nil());
-            server.broadcast_some(packet, resolved).await?;
+            server.broadcast_some(packet, resolved);
 
             Ok(HandleResult::Ok)
         }
@@ -81,14 +183,34 @@ pub(super) async fn handle_command(
             };
 
             let packet = packet.into_packet(Uuid::nil());
-            server.broadcast(packet).await?;
+            server.broadcast(packet);
 
             Ok(HandleResult::Ok)
         }
 
         Command::Moon(MoonCommand::List) => {
-            // TODO
-            bail!("not yet implemented")
+            let moons = server.list_moons().await;
+            if moons.is_empty() {
+                info!("No moons have been collected");
+                return Ok(HandleResult::Ok);
+            }
+
+            let mut stages: BTreeMap<&str, Vec<&Moon>> = BTreeMap::new();
+            for moon in &moons {
+                let stage = moon.stage.as_deref().unwrap_or("Unknown Stage");
+                stages.entry(stage).or_default().push(moon);
+            }
+
+            let mut output = format!("{} moons collected", moons.len());
+            for (stage, moons) in stages {
+                let _ = write!(output, "\n{stage}:");
+                for moon in moons {
+                    let _ = write!(output, "\n  {moon}");
+                }
+            }
+
+            info!("{output}");
+            Ok(HandleResult::Ok)
         }
 
         Command::Moon(MoonCommand::Sync) => {
@@ -131,15 +253,215 @@ pub(super) async fn handle_command(
             Ok(HandleResult::Ok)
         }
 
-        Command::Moon(MoonCommand::Add { id }) => {
-            // TODO
-            bail!("not yet implemented")
+        Command::Moon(MoonCommand::Add { id, grand, players }) => {
+            let resolved = if players.is_empty() {
+                None
+            } else {
+                let resolved = server.resolve_players(players);
+                if resolved.is_empty() {
+                    warn!("No players selected! (Use * to select all players)");
+                    return Ok(HandleResult::Ok);
+                }
+
+                Some(resolved)
+            };
+
+            let given = server.give_moon(id, grand, resolved).await?;
+            info!("Added moon {id}, sent to {} player(s)", given.len());
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Profile(ProfileCommand::List) => {
+            let profiles = server.list_profiles().await;
+            if profiles.is_empty() {
+                info!("No players have joined yet");
+                return Ok(HandleResult::Ok);
+            }
+
+            let mut output = format!("{} known players", profiles.len());
+            for profile in &profiles {
+                let _ = write!(output, "\n  {profile}");
+            }
+
+            info!("{output}");
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Profile(ProfileCommand::Show { player }) => {
+            let profiles = server.find_profiles(&player).await;
+            if profiles.is_empty() {
+                warn!("No profile found for {player}");
+                return Ok(HandleResult::Ok);
+            }
+
+            for profile in profiles {
+                let mut output = profile.to_string();
+                let _ = write!(output, "\n  names: {}", profile.names.join(", "));
+                let _ = write!(
+                    output,
+                    "\n  first seen: {}",
+                    profile.first_seen.format("%Y-%m-%d %H:%M:%S UTC")
+                );
+
+                if let Some(stage) = &profile.stage {
+                    let _ = write!(output, "\n  last stage: {stage}");
+                }
+
+                if let Some(costume) = &profile.costume {
+                    let _ = write!(output, "\n  costume: {} / {}", costume.body, costume.cap);
+                }
+
+                let moons = profile.moons.iter().map(ToString::to_string);
+                let _ = write!(
+                    output,
+                    "\n  moons ({}): {}",
+                    profile.moons.len(),
+                    moons.collect::<Vec<_>>().join(", ")
+                );
+
+                info!("{output}");
+            }
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Tag(TagCommand::Start) => {
+            if server.start_tag().await {
+                info!("Started hide and seek round");
+            } else {
+                warn!("No seekers selected! (Use tag seeker to select seekers)");
+            }
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Tag(TagCommand::Seeker { players }) => {
+            let resolved = server.resolve_players(players);
+            if resolved.is_empty() {
+                warn!("No players selected! (Use * to select all players)");
+                return Ok(HandleResult::Ok);
+            }
+
+            let count = resolved.len();
+            server.set_tag_seekers(resolved).await;
+            info!("Set {count} seeker(s)");
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Tag(TagCommand::Time { minutes, seconds }) => {
+            let round_time = Duration::from_secs(u64::from(minutes) * 60 + u64::from(seconds));
+            if server.set_tag_time(round_time).await {
+                info!("Set hide and seek round time to {minutes}:{seconds:02}, starting from the next round");
+            } else {
+                info!("Set hide and seek round time to {minutes}:{seconds:02}");
+            }
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Tag(TagCommand::Stop) => {
+            if server.stop_tag().await {
+                info!("Stopped hide and seek round");
+            } else {
+                warn!("No hide and seek round is running!");
+            }
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Whitelist(WhitelistCommand::Add { players }) => {
+            let ids = resolve_ids(&server, players);
+            if ids.is_empty() {
+                warn!("No players selected! (Use * to select all players)");
+                return Ok(HandleResult::Ok);
+            }
+
+            let mut config = config.write().await;
+            config.whitelist.ids.extend(&ids);
+            config.save().await?;
+
+            info!("Added {} player(s) to the whitelist", ids.len());
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Whitelist(WhitelistCommand::Remove { players }) => {
+            let ids = resolve_ids(&server, players);
+
+            let mut config = config.write().await;
+            let len = config.whitelist.ids.len();
+            config.whitelist.ids.retain(|id| !ids.contains(id));
+
+            let removed = len - config.whitelist.ids.len();
+            config.save().await?;
+
+            info!("Removed {removed} player(s) from the whitelist");
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Whitelist(WhitelistCommand::List) => {
+            let (enabled, ids) = {
+                let config = config.read().await;
+                (config.whitelist.enabled, config.whitelist.ids.clone())
+            };
+
+            let state = if enabled { "enabled" } else { "disabled" };
+            let mut output = format!("Whitelist is {state}, {} player(s)", ids.len());
+
+            for id in ids {
+                let name = match server.find_profiles(&id.to_string()).await.first() {
+                    Some(profile) => profile.name.clone(),
+                    None => "unknown".into(),
+                };
+
+                let _ = write!(output, "\n  {name}/{id}");
+            }
+
+            info!("{output}");
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Whitelist(WhitelistCommand::On) => {
+            let mut config = config.write().await;
+            config.whitelist.enabled = true;
+            config.save().await?;
+
+            if config.whitelist.ids.is_empty() {
+                warn!("Whitelist enabled, but it is empty so nobody can join");
+            } else {
+                info!("Whitelist enabled");
+            }
+
+            Ok(HandleResult::Ok)
+        }
+
+        Command::Whitelist(WhitelistCommand::Off) => {
+            let mut config = config.write().await;
+            config.whitelist.enabled = false;
+            config.save().await?;
+
+            info!("Whitelist disabled");
+            Ok(HandleResult::Ok)
         }
     }
 }
 
+/// UUIDs given directly, plus connected players matched by name
+fn resolve_ids(server: &Server, players: Vec<String>) -> HashSet<Uuid> {
+    let ids = players
+        .iter()
+        .filter_map(|player| player.parse::<Uuid>().ok())
+        .collect::<Vec<_>>();
+
+    let mut resolved = server.resolve_players(players);
+    resolved.extend(ids);
+
+    resolved
+}
+
 #[derive(Debug, PartialEq, Eq)]
-pub(super) enum HandleResult {
+pub enum HandleResult {
     Ok,
     Exit,
 }
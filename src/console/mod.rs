@@ -1,5 +1,5 @@
-mod commands;
-mod handler;
+pub mod commands;
+pub mod handler;
 pub mod reader;
 mod stage;
 pub mod writer;
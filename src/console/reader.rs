@@ -17,7 +17,9 @@ pub async fn read_loop<H: Helper>(
     config: SharedConfig,
 ) -> Result<()> {
     loop {
-        match rl.readline("> ") {
+        // Reading blocks, move other tasks off this worker thread while waiting
+        let line = tokio::task::block_in_place(|| rl.readline("> "));
+        match line {
             Ok(line) => {
                 rl.add_history_entry(&line);
 
@@ -39,17 +41,21 @@ pub async fn read_loop<H: Helper>(
                             "An error occurred while processing that command\n{:?}",
                             error
                         );
-
-                        continue;
                     }
                 };
             }
 
             Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
-            Err(err) => return Err(err.into()),
+            Err(err) => {
+                // Otherwise the server keeps running with no console to stop it
+                error!("Failed to read a console command, stopping the server\n{err:?}");
+                server.stop();
+
+                return Err(err.into());
+            }
         }
     }
 
-    tracing::info!("Exiting...");
-    std::process::exit(0);
+    server.stop();
+    Ok(())
 }
@@ -38,9 +38,8 @@ impl Write for ThreadWriter {
     }
 }
 
-#[allow(clippy::unused_async)]
 pub async fn write_loop(mut printer: impl ExternalPrinter, rx: Receiver<String>) -> Result<()> {
-    while let Ok(msg) = rx.recv() {
+    while let Ok(msg) = rx.recv_async().await {
         printer.print(msg)?;
     }
 
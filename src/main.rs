@@ -1,18 +1,16 @@
 #![forbid(unsafe_code)]
-#![deny(private_in_public)]
+#![deny(private_interfaces, private_bounds)]
 #![warn(
     clippy::all,
     clippy::dbg_macro,
     clippy::todo,
-    clippy::empty_enum,
+    clippy::empty_enums,
     clippy::enum_glob_use,
     clippy::unused_self,
     clippy::needless_continue,
     clippy::needless_borrow,
     clippy::match_wildcard_for_single_variants,
     clippy::if_let_mutex,
-    clippy::mismatched_target_os,
-    clippy::match_on_vec_items,
     clippy::imprecise_flops,
     clippy::suboptimal_flops,
     clippy::lossy_float_literal,
@@ -33,7 +31,9 @@
     missing_debug_implementations
 )]
 
+use std::future::Future;
 use std::net::IpAddr;
+use std::sync::Arc;
 
 use clap::{ArgAction, Parser};
 use color_eyre::Result;
@@ -42,12 +42,14 @@ use console::writer::{self, ThreadWriter};
 use once_cell::sync::Lazy;
 use rustyline::Editor;
 use tracing_error::ErrorLayer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
 use crate::config::Config;
 use crate::server::Server;
 
+mod api;
 mod config;
 mod console;
 mod moons;
@@ -56,7 +58,10 @@ mod peer;
 mod peers;
 mod player;
 mod players;
+mod profiles;
+mod rate_limit;
 mod server;
+mod tag;
 
 static VERSION: Lazy<String> = Lazy::new(|| {
     let mut version = format!("v{}", env!("CARGO_PKG_VERSION"));
@@ -83,6 +88,11 @@ pub struct Args {
     /// Server bind host [default: 1027]
     #[clap(short, long)]
     port: Option<u16>,
+
+    /// Log to stdout without reading console commands, eg: for running as a service.
+    /// The server exits on SIGINT or SIGTERM
+    #[clap(long)]
+    headless: bool,
 }
 
 #[tokio::main]
@@ -90,14 +100,21 @@ async fn main() -> Result<()> {
     color_eyre::install()?;
     let args = Args::parse();
 
-    let mut rl = Editor::<()>::new()?;
-    let printer = rl.create_external_printer()?;
-    let (writer, rx) = ThreadWriter::new();
+    // Logs are printed above the prompt while the console is reading commands
+    let (writer, console) = if args.headless {
+        (BoxMakeWriter::new(std::io::stdout), None)
+    } else {
+        let mut rl = Editor::<()>::new()?;
+        let printer = rl.create_external_printer()?;
+        let (writer, rx) = ThreadWriter::new();
+
+        (BoxMakeWriter::new(writer), Some((rl, printer, rx)))
+    };
 
     let pkg_name = env!("TRACING_FMT");
     let filter = match args.verbose {
         #[cfg(debug_assertions)]
-        0 | 1 | 2 => format!("{}=debug", pkg_name),
+        0..=2 => format!("{}=debug", pkg_name),
 
         #[cfg(not(debug_assertions))]
         0 => format!("{}=info", pkg_name),
@@ -111,6 +128,7 @@ async fn main() -> Result<()> {
     let filter = EnvFilter::new(filter);
     let fmt = fmt::layer()
         .with_target(args.verbose >= 2)
+        .with_ansi(!args.headless)
         .with_writer(writer);
 
     tracing_subscriber::registry()
@@ -122,19 +140,73 @@ async fn main() -> Result<()> {
     let config = Config::load().await?.shared();
     let server = Server::new(&args, config.clone()).await?;
 
-    let listen_handle = tokio::spawn(server.clone().listen());
-    let process_handle = tokio::spawn(server.clone().process_packets());
-    let moon_sync_handle = tokio::spawn(server.clone().sync_moons_loop());
-    let reader_handle = tokio::spawn(reader::read_loop(rl, server, config));
-    let writer_handle = tokio::spawn(writer::write_loop(printer, rx));
+    let listeners = [
+        tokio::spawn(stop_on_error(server.clone(), server.clone().listen())),
+        tokio::spawn(stop_on_error(server.clone(), server.clone().listen_udp())),
+    ];
+
+    tokio::spawn(server.clone().sync_moons_loop());
+    tokio::spawn(server.clone().tag_loop());
+    tokio::spawn(server.clone().watch_config());
+    tokio::spawn(stop_on_signal(server.clone()));
+
+    let (api_server, api_config) = (server.clone(), config.clone());
+    tokio::spawn(async move {
+        if let Err(error) = api::listen(api_server, api_config).await {
+            tracing::error!("Admin API stopped\n{error:?}");
+        }
+    });
+
+    if let Some((rl, printer, rx)) = console {
+        tokio::spawn(writer::write_loop(printer, rx));
+        tokio::spawn(reader::read_loop(rl, server.clone(), config));
+    }
+
+    // Stopped by the console, a signal or the admin API
+    server.stopped().await;
+    server.shutdown().await?;
+
+    // Listeners return once the server stops, unless one failed and stopped it
+    let mut failed = false;
+    for listener in listeners {
+        failed |= !matches!(listener.await, Ok(Ok(())));
+    }
+
+    // The console can still be blocked reading a line, which would keep the runtime alive
+    tracing::info!("Exiting...");
+    std::process::exit(i32::from(failed));
+}
+
+/// Stop the server if a listener it can't run without fails
+async fn stop_on_error(
+    server: Arc<Server>,
+    listener: impl Future<Output = Result<()>>,
+) -> Result<()> {
+    let result = listener.await;
+    if let Err(error) = &result {
+        tracing::error!("Listener stopped, stopping the server\n{error:?}");
+        server.stop();
+    }
+
+    result
+}
+
+/// Stop the server on SIGINT or SIGTERM
+async fn stop_on_signal(server: Arc<Server>) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => (),
+        }
+    }
 
-    let _ = futures::join!(
-        listen_handle,
-        process_handle,
-        moon_sync_handle,
-        reader_handle,
-        writer_handle
-    );
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
 
+    server.stop();
     Ok(())
 }
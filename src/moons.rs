@@ -1,31 +1,175 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
 
+use chrono::{DateTime, Utc};
 use color_eyre::Result;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::fs;
+use uuid::Uuid;
 
 use crate::config::SharedConfig;
+use crate::player::Player;
 
 pub type MoonMap = BTreeSet<i32>;
 
+// region: Moon
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Moon {
+    pub id: i32,
+
+    #[serde(default)]
+    pub is_grand: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kingdom: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collected_at: Option<DateTime<Utc>>,
+
+    // Tables must come after values when serialized as TOML
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collected_by: Option<Collector>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Collector {
+    pub id: Uuid,
+    pub name: String,
+}
+
+impl Moon {
+    /// Moon that wasn't collected by a player, eg: added from the console
+    #[inline]
+    pub fn new(id: i32, is_grand: bool) -> Self {
+        Self {
+            id,
+            is_grand,
+            collected_by: None,
+            collected_at: Some(Utc::now()),
+            stage: None,
+            kingdom: None,
+        }
+    }
+
+    #[inline]
+    pub fn collected(id: i32, is_grand: bool, player: &Player) -> Self {
+        let collector = Collector {
+            id: player.id,
+            name: player.name.clone(),
+        };
+
+        Self {
+            id,
+            is_grand,
+            collected_by: Some(collector),
+            collected_at: Some(Utc::now()),
+            stage: player.stage().map(ToOwned::to_owned),
+            kingdom: player.last_kingdom.clone(),
+        }
+    }
+}
+
+impl Display for Moon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)?;
+        if self.is_grand {
+            write!(f, " (grand)")?;
+        }
+
+        match &self.collected_by {
+            Some(collector) => write!(f, " collected by {}/{}", collector.name, collector.id)?,
+            None => write!(f, " added by server")?,
+        }
+
+        if let Some(collected_at) = &self.collected_at {
+            write!(f, " at {}", collected_at.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Older moon files only stored a list of IDs
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredMoon {
+    Id(i32),
+    Moon(Moon),
+}
+
+impl From<StoredMoon> for Moon {
+    #[inline]
+    fn from(stored: StoredMoon) -> Self {
+        match stored {
+            StoredMoon::Id(id) => Self {
+                id,
+                is_grand: false,
+                collected_by: None,
+                collected_at: None,
+                stage: None,
+                kingdom: None,
+            },
+
+            StoredMoon::Moon(moon) => moon,
+        }
+    }
+}
+
+fn serialize_moons<S: Serializer>(map: &BTreeMap<i32, Moon>, ser: S) -> Result<S::Ok, S::Error> {
+    ser.collect_seq(map.values())
+}
+
+fn deserialize_moons<'de, D: Deserializer<'de>>(de: D) -> Result<BTreeMap<i32, Moon>, D::Error> {
+    let stored = Vec::<StoredMoon>::deserialize(de)?;
+    let map = stored
+        .into_iter()
+        .map(Moon::from)
+        .map(|moon| (moon.id, moon))
+        .collect();
+
+    Ok(map)
+}
+// endregion
+
+// region: Moons
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Moons {
-    #[serde(rename = "moons")]
-    map: MoonMap,
+    #[serde(
+        rename = "moons",
+        serialize_with = "serialize_moons",
+        deserialize_with = "deserialize_moons"
+    )]
+    map: BTreeMap<i32, Moon>,
 
     #[serde(skip)]
     config: SharedConfig,
 }
 
 impl Moons {
-    pub async fn insert(&mut self, id: i32) -> Result<()> {
-        self.map.insert(id);
+    /// Insert a moon, keeping the original record if it was already collected
+    pub async fn insert(&mut self, moon: Moon) -> Result<()> {
+        if self.map.contains_key(&moon.id) {
+            return Ok(());
+        }
+
+        self.map.insert(moon.id, moon);
         self.save().await
     }
 
     #[inline]
-    pub fn difference(&self, other: &MoonMap) -> MoonMap {
-        self.map.difference(other).copied().collect()
+    pub fn all_moons(&self) -> impl Iterator<Item = &Moon> + '_ {
+        self.map.values()
+    }
+
+    #[inline]
+    pub fn difference(&self, other: &MoonMap) -> Vec<&Moon> {
+        self.map
+            .values()
+            .filter(|moon| !other.contains(&moon.id))
+            .collect()
     }
 
     #[inline]
@@ -64,7 +208,7 @@ impl Moons {
         Ok(())
     }
 
-    async fn save(&self) -> Result<()> {
+    pub async fn save(&self) -> Result<()> {
         let cfg = self.config.read().await;
         let path = &cfg.moons.persist_file;
 
@@ -77,3 +221,42 @@ impl Moons {
     }
     // endregion
 }
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_legacy_moons() {
+        let moons: Moons = toml::from_str("moons = [1, 2, 3]").unwrap();
+        let ids: Vec<_> = moons.all_moons().map(|moon| moon.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+
+        for moon in moons.all_moons() {
+            assert!(!moon.is_grand);
+            assert!(moon.collected_by.is_none());
+            assert!(moon.collected_at.is_none());
+        }
+    }
+
+    #[test]
+    fn test_mixed_moons() {
+        let body = r#"
+            moons = [
+                1,
+                { id = 2, is_grand = true, stage = "CapWorldHomeStage", collected_by = { id = "00000000-0000-0000-0000-000000000001", name = "Mario" } },
+            ]
+        "#;
+
+        let moons: Moons = toml::from_str(body).unwrap();
+        let moon = moons.all_moons().find(|moon| moon.id == 2).unwrap();
+        assert!(moon.is_grand);
+        assert_eq!(moon.stage.as_deref(), Some("CapWorldHomeStage"));
+        assert_eq!(moon.collected_by.as_ref().unwrap().name, "Mario");
+
+        let saved = toml::to_string_pretty(&moons).unwrap();
+        let reloaded: Moons = toml::from_str(&saved).unwrap();
+        assert_eq!(reloaded.all_moons().count(), 2);
+    }
+}
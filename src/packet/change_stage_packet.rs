@@ -3,7 +3,7 @@ use smoo_derive::Packet;
 use super::fixed_string::FixedString;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Packet)]
-#[packet("ChangeStage")]
+#[packet("ChangeStage", padding = 2)]
 pub struct ChangeStagePacket {
     pub stage: FixedString<0x30>,
     pub id: FixedString<0x10>,
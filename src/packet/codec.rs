@@ -1,50 +1,123 @@
 use bytes::{Buf, BytesMut};
-use color_eyre::Report;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use tokio_util::codec::{Decoder, Encoder};
+use tracing::warn;
 
+use super::error::ProtocolError;
 use super::header::{Packet, PartialPacket};
 use super::traits::PacketBytes;
 
-pub struct PacketCodec;
+/// Largest packet body that will be accepted
+pub const MAX_BODY_LENGTH: usize = 1024;
+
+/// How [`PacketCodec`] handles frames it can't decode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameMode {
+    /// Return an error, closing the connection
+    #[default]
+    Strict,
+
+    /// Skip the frame and continue decoding from the next one
+    Lenient,
+}
+
+#[derive(Debug, Error)]
+pub enum CodecError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("frame body is too large ({body_length} > {MAX_BODY_LENGTH} bytes)")]
+    FrameTooLarge { body_length: usize },
+
+    #[error("invalid packet: {0}")]
+    InvalidPacket(#[from] ProtocolError),
+}
+
+#[derive(Debug, Default)]
+pub struct PacketCodec {
+    mode: FrameMode,
+
+    /// Body bytes of an oversized frame that still need to be skipped
+    skip: usize,
+}
+
+impl PacketCodec {
+    #[inline]
+    pub fn new(mode: FrameMode) -> Self {
+        Self { mode, skip: 0 }
+    }
+
+    /// Skip as much of an oversized frame as possible, returns `true` once done
+    fn skip_frame(&mut self, buf: &mut BytesMut) -> bool {
+        let skipped = self.skip.min(buf.remaining());
+        buf.advance(skipped);
+        self.skip -= skipped;
+
+        self.skip == 0
+    }
+}
 
 impl Decoder for PacketCodec {
     type Item = Packet;
-    type Error = Report;
+    type Error = CodecError;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if buf.remaining() < Packet::buf_size() {
-            buf.reserve(Packet::buf_size());
+        loop {
+            if !self.skip_frame(buf) {
+                return Ok(None);
+            }
 
-            return Ok(None);
-        }
+            let header_len = Packet::buf_size();
+            if buf.remaining() < header_len {
+                buf.reserve(header_len);
 
-        let partial = PartialPacket::from_bytes(buf)?;
-        let body_len = partial.body_length as usize;
+                return Ok(None);
+            }
 
-        // Catch obviously wrong packets
-        if body_len > 1024 {
-            buf.clear();
-            buf.reserve(Packet::buf_size());
+            // Only consume the header once the whole frame has arrived
+            let partial = PartialPacket::from_bytes(&mut &buf[..header_len])?;
 
-            return Ok(None);
-        }
+            let body_length = partial.body_length as usize;
+            if body_length > MAX_BODY_LENGTH {
+                match self.mode {
+                    FrameMode::Strict => return Err(CodecError::FrameTooLarge { body_length }),
+                    FrameMode::Lenient => {
+                        warn!(body_length, "skipping oversized frame");
 
-        if buf.remaining() < body_len {
-            buf.reserve(body_len);
+                        buf.advance(header_len);
+                        self.skip = body_length;
 
-            return Ok(None);
-        }
+                        continue;
+                    }
+                }
+            }
+
+            let frame_len = header_len + body_length;
+            if buf.remaining() < frame_len {
+                buf.reserve(frame_len - buf.remaining());
 
-        let mut body = buf.split_to(body_len);
-        let packet = partial.upgrade(&mut body)?;
-        buf.reserve(Packet::buf_size());
+                return Ok(None);
+            }
 
-        Ok(Some(packet))
+            buf.advance(header_len);
+            let body = buf.split_to(body_length).freeze();
+
+            match partial.upgrade(body) {
+                Ok(packet) => return Ok(Some(packet)),
+                Err(error) if self.mode == FrameMode::Lenient => {
+                    warn!(packet_id = partial.packet_id, %error, "skipping invalid packet");
+                }
+
+                Err(error) => return Err(CodecError::InvalidPacket(error)),
+            }
+        }
     }
 }
 
 impl Encoder<Packet> for PacketCodec {
-    type Error = Report;
+    type Error = CodecError;
 
     #[inline]
     fn encode(&mut self, item: Packet, buf: &mut BytesMut) -> Result<(), Self::Error> {
@@ -1,8 +1,7 @@
 use bytes::Buf;
-use color_eyre::eyre::eyre;
-use color_eyre::Result;
 use smoo_derive::Packet;
 
+use super::error::{ProtocolError, Result};
 use super::fixed_string::FixedString;
 use super::traits::PacketBytes;
 
@@ -33,7 +32,10 @@ impl PacketBytes for ConnectionType {
             0 => Ok(ConnectionType::Init),
             1 => Ok(ConnectionType::Reconnect),
 
-            _ => Err(eyre!("invalid connection type: {id}")),
+            value => Err(ProtocolError::InvalidEnum {
+                ty: "connection type",
+                value,
+            }),
         }
     }
 }
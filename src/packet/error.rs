@@ -0,0 +1,43 @@
+use std::str::Utf8Error;
+
+use bytes::Buf;
+use thiserror::Error;
+
+pub type Result<T, E = ProtocolError> = std::result::Result<T, E>;
+
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("not enough bytes to read {ty} ({needed} needed, {remaining} remaining)")]
+    ShortRead {
+        ty: &'static str,
+        needed: usize,
+        remaining: usize,
+    },
+
+    #[error("packet body is {actual} bytes, but the header declares {expected}")]
+    LengthMismatch { expected: usize, actual: usize },
+
+    #[error("invalid {ty}: {value}")]
+    InvalidEnum { ty: &'static str, value: u32 },
+
+    #[error("invalid utf-8 in string: {0}")]
+    InvalidUtf8(#[from] Utf8Error),
+
+    #[error("string is too long ({len} > {max} bytes)")]
+    StringTooLong { len: usize, max: usize },
+}
+
+/// Ensure `buf` has at least `needed` bytes left before reading a `ty`
+#[inline]
+pub fn ensure_remaining<T: Buf>(buf: &T, needed: usize, ty: &'static str) -> Result<()> {
+    let remaining = buf.remaining();
+    if remaining < needed {
+        return Err(ProtocolError::ShortRead {
+            ty,
+            needed,
+            remaining,
+        });
+    }
+
+    Ok(())
+}
@@ -2,9 +2,8 @@ use std::fmt::Display;
 use std::str::{FromStr, Utf8Error};
 
 use bytes::{Buf, BufMut, BytesMut};
-use color_eyre::eyre::eyre;
-use color_eyre::{Report, Result};
 
+use super::error::{ProtocolError, Result};
 use super::PacketBytes;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -15,7 +14,9 @@ pub struct FixedString<const N: usize> {
 impl<const N: usize> FixedString<N> {
     #[inline]
     pub fn try_as_str(&self) -> Result<&str, Utf8Error> {
-        std::str::from_utf8(&self.inner).map(|s| s.trim_matches('\0'))
+        // Anything after the first null byte is left over from the client's buffer
+        let len = self.inner.iter().position(|b| *b == 0).unwrap_or(N);
+        std::str::from_utf8(&self.inner[..len])
     }
 
     #[inline]
@@ -47,12 +48,15 @@ impl<const N: usize> PacketBytes for FixedString<N> {
     #[inline]
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
         let inner = <[u8; N] as PacketBytes>::from_bytes(buf)?;
-        Ok(Self { inner })
+        let string = Self { inner };
+        string.try_as_str()?;
+
+        Ok(string)
     }
 }
 
 impl<const N: usize> FromStr for FixedString<N> {
-    type Err = Report;
+    type Err = ProtocolError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let string = s.to_owned();
@@ -62,7 +66,7 @@ impl<const N: usize> FromStr for FixedString<N> {
         let len = bytes.len();
 
         if len > max_len {
-            Err(eyre!("string is too long"))
+            Err(ProtocolError::StringTooLong { len, max: max_len })
         } else {
             // Pad
             let pad_len = max_len - len;
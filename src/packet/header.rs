@@ -1,28 +1,33 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use color_eyre::{Report, Result};
 use uuid::Uuid;
 
-use super::cap_packet::CapPacket;
-use super::capture_packet::CapturePacket;
-use super::change_stage_packet::ChangeStagePacket;
-use super::connect_packet::ConnectPacket;
-use super::costume_packet::CostumePacket;
-use super::game_packet::GamePacket;
-use super::init_packet::InitPacket;
-use super::moon_packet::MoonPacket;
-use super::player_packet::PlayerPacket;
-use super::tag_packet::TagPacket;
-use super::PacketBytes;
+use super::error::{ProtocolError, Result};
+use super::{
+    CapPacket, CapturePacket, ChangeStagePacket, ConnectPacket, CostumePacket, GamePacket,
+    InitPacket, MoonPacket, PacketBytes, PlayerPacket, TagPacket, UdpInitPacket,
+};
 
 // region: PacketHeader
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Packet {
     pub id: Uuid,
     pub data: PacketData,
+
+    /// Body bytes following the known fields, sent by newer clients
+    pub trailing: Bytes,
 }
 
 impl Packet {
-    pub fn to_bytes(self) -> Bytes {
+    #[inline]
+    pub fn new(id: Uuid, data: PacketData) -> Self {
+        Self {
+            id,
+            data,
+            trailing: Bytes::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
         let mut buf = BytesMut::with_capacity(128);
         self.write_bytes(&mut buf);
 
@@ -39,98 +44,28 @@ impl Packet {
         std::mem::size_of::<Uuid>() + std::mem::size_of::<u16>() + std::mem::size_of::<u16>()
     }
 
-    fn read_body<T: Buf>(id: Uuid, packet_id: u16, buf: &mut T) -> Result<Packet> {
-        match packet_id {
-            1 => {
-                let packet = InitPacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            2 => {
-                let packet = PlayerPacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            3 => {
-                let packet = CapPacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            4 => {
-                let packet = GamePacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            5 => {
-                let packet = TagPacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            6 => {
-                let packet = ConnectPacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            7 => Ok(Packet {
-                id,
-                data: PacketData::Disconnect,
-            }),
-
-            8 => {
-                let packet = CostumePacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            9 => {
-                let packet = MoonPacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            10 => {
-                let packet = CapturePacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
-
-            11 => {
-                let packet = ChangeStagePacket::from_bytes(buf)?;
-                Ok(Packet {
-                    id,
-                    data: packet.into(),
-                })
-            }
+    fn read_data<T: Buf>(packet_id: u16, buf: &mut T) -> Result<PacketData> {
+        let data = match packet_id {
+            1 => InitPacket::from_bytes(buf)?.into(),
+            2 => PlayerPacket::from_bytes(buf)?.into(),
+            3 => CapPacket::from_bytes(buf)?.into(),
+            4 => GamePacket::from_bytes(buf)?.into(),
+            5 => TagPacket::from_bytes(buf)?.into(),
+            6 => ConnectPacket::from_bytes(buf)?.into(),
+            7 => PacketData::Disconnect,
+            8 => CostumePacket::from_bytes(buf)?.into(),
+            9 => MoonPacket::from_bytes(buf)?.into(),
+            10 => CapturePacket::from_bytes(buf)?.into(),
+            11 => ChangeStagePacket::from_bytes(buf)?.into(),
+            13 => UdpInitPacket::from_bytes(buf)?.into(),
+            14 => PacketData::HolePunch,
+            packet_id => PacketData::Raw {
+                packet_id,
+                body: buf.copy_to_bytes(buf.remaining()),
+            },
+        };
 
-            _ => Ok(Packet {
-                id,
-                data: PacketData::Unknown,
-            }),
-        }
+        Ok(data)
     }
 }
 
@@ -143,7 +78,10 @@ impl PacketBytes for Packet {
         written += packet_id.write_bytes(buf);
 
         let mut packet_buf = BytesMut::with_capacity(128);
-        let packet_byte_count = self.data.write_bytes(&mut packet_buf);
+        let mut packet_byte_count = self.data.write_bytes(&mut packet_buf);
+
+        packet_buf.put(&self.trailing[..]);
+        packet_byte_count += self.trailing.len();
 
         let packet_byte_short = packet_byte_count as u16;
         written += packet_byte_short.write_bytes(buf);
@@ -155,12 +93,15 @@ impl PacketBytes for Packet {
     }
 
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
-        let id = <Uuid as PacketBytes>::from_bytes(buf)?;
-        let packet_id = u16::from_bytes(buf)?;
+        let partial = PartialPacket::from_bytes(buf)?;
+
+        let expected = partial.body_length as usize;
+        let actual = buf.remaining();
+        if actual < expected {
+            return Err(ProtocolError::LengthMismatch { expected, actual });
+        }
 
-        // Packet length
-        let _ = u16::from_bytes(buf)?;
-        Self::read_body(id, packet_id, buf)
+        partial.upgrade(buf.copy_to_bytes(expected))
     }
 }
 
@@ -172,7 +113,7 @@ impl From<Packet> for Bytes {
 }
 
 impl TryFrom<Bytes> for Packet {
-    type Error = Report;
+    type Error = ProtocolError;
 
     #[inline]
     fn try_from(buf: Bytes) -> Result<Self, Self::Error> {
@@ -182,9 +123,14 @@ impl TryFrom<Bytes> for Packet {
 // endregion
 
 // region: PacketData
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PacketData {
-    Unknown,
+    /// Packet type the server doesn't know, kept as-is so it can be relayed
+    Raw {
+        packet_id: u16,
+        body: Bytes,
+    },
+
     Init(InitPacket),
     Player(PlayerPacket),
     Cap(CapPacket),
@@ -196,13 +142,15 @@ pub enum PacketData {
     Moon(MoonPacket),
     Capture(CapturePacket),
     ChangeStage(ChangeStagePacket),
+    UdpInit(UdpInitPacket),
+    HolePunch,
 }
 
 impl PacketData {
     #[inline]
     pub fn id(&self) -> u16 {
         match self {
-            PacketData::Unknown => 0,
+            PacketData::Raw { packet_id, .. } => *packet_id,
             PacketData::Init(_) => 1,
             PacketData::Player(_) => 2,
             PacketData::Cap(_) => 3,
@@ -214,6 +162,29 @@ impl PacketData {
             PacketData::Moon(_) => 9,
             PacketData::Capture(_) => 10,
             PacketData::ChangeStage(_) => 11,
+            PacketData::UdpInit(_) => 13,
+            PacketData::HolePunch => 14,
+        }
+    }
+
+    /// Lowercase name of the packet type, eg: for config keys
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        match self {
+            PacketData::Raw { .. } => "raw",
+            PacketData::Init(_) => "init",
+            PacketData::Player(_) => "player",
+            PacketData::Cap(_) => "cap",
+            PacketData::Game(_) => "game",
+            PacketData::Tag(_) => "tag",
+            PacketData::Connect(_) => "connect",
+            PacketData::Disconnect => "disconnect",
+            PacketData::Costume(_) => "costume",
+            PacketData::Moon(_) => "moon",
+            PacketData::Capture(_) => "capture",
+            PacketData::ChangeStage(_) => "change_stage",
+            PacketData::UdpInit(_) => "udp_init",
+            PacketData::HolePunch => "hole_punch",
         }
     }
 }
@@ -222,7 +193,12 @@ impl PacketBytes for PacketData {
     fn write_bytes(&self, buf: &mut BytesMut) -> usize {
         match self {
             // Do nothing
-            PacketData::Unknown | PacketData::Disconnect => 0,
+            PacketData::Disconnect | PacketData::HolePunch => 0,
+
+            PacketData::Raw { body, .. } => {
+                buf.put(&body[..]);
+                body.len()
+            }
 
             PacketData::Init(packet) => packet.write_bytes(buf),
             PacketData::Player(packet) => packet.write_bytes(buf),
@@ -234,6 +210,7 @@ impl PacketBytes for PacketData {
             PacketData::Moon(packet) => packet.write_bytes(buf),
             PacketData::Capture(packet) => packet.write_bytes(buf),
             PacketData::ChangeStage(packet) => packet.write_bytes(buf),
+            PacketData::UdpInit(packet) => packet.write_bytes(buf),
         }
     }
 
@@ -252,9 +229,27 @@ pub struct PartialPacket {
 }
 
 impl PartialPacket {
-    #[inline]
-    pub fn upgrade<T: Buf>(self, buf: &mut T) -> Result<Packet> {
-        Packet::read_body(self.id, self.packet_id, buf)
+    /// Parse the packet body, which must be exactly `body_length` bytes
+    ///
+    /// Bytes left over after the known fields are kept as [`Packet::trailing`],
+    /// so packets from newer clients can still be relayed unchanged.
+    pub fn upgrade(self, mut body: Bytes) -> Result<Packet> {
+        let expected = self.body_length as usize;
+        if body.len() != expected {
+            return Err(ProtocolError::LengthMismatch {
+                expected,
+                actual: body.len(),
+            });
+        }
+
+        let data = Packet::read_data(self.packet_id, &mut body)?;
+        let packet = Packet {
+            id: self.id,
+            data,
+            trailing: body,
+        };
+
+        Ok(packet)
     }
 }
 
@@ -1,4 +1,5 @@
 mod codec;
+mod error;
 mod fixed_string;
 mod header;
 mod traits;
@@ -14,13 +15,15 @@ mod init_packet;
 mod moon_packet;
 mod player_packet;
 mod tag_packet;
+mod udp_init_packet;
 
 pub use cap_packet::CapPacket;
 pub use capture_packet::CapturePacket;
 pub use change_stage_packet::ChangeStagePacket;
-pub use codec::PacketCodec;
+pub use codec::{FrameMode, PacketCodec, MAX_BODY_LENGTH};
 pub use connect_packet::{ConnectPacket, ConnectionType};
 pub use costume_packet::CostumePacket;
+pub use error::ProtocolError;
 pub use fixed_string::FixedString;
 pub use game_packet::GamePacket;
 pub use header::*;
@@ -29,12 +32,16 @@ pub use moon_packet::MoonPacket;
 pub use player_packet::PlayerPacket;
 pub use tag_packet::TagPacket;
 pub use traits::*;
+pub use udp_init_packet::UdpInitPacket;
 
 #[cfg(test)]
 mod tests {
+    use bytes::{BufMut, BytesMut};
     use glam::{EulerRot, Quat, Vec3};
+    use tokio_util::codec::{Decoder, Encoder};
     use uuid::Uuid;
 
+    use super::codec::CodecError;
     use super::*;
 
     macro_rules! test_packet {
@@ -144,6 +151,18 @@ mod tests {
         test_packet!(data, 0x38);
     }
 
+    #[test]
+    fn test_tag_packet() {
+        let data = TagPacket {
+            update_bits: TagPacket::UPDATE_TIME | TagPacket::UPDATE_STATE,
+            is_it: true,
+            seconds: 59,
+            minutes: 420,
+        };
+
+        test_packet!(data, 5);
+    }
+
     #[test]
     fn test_moon_packet() {
         let data = MoonPacket {
@@ -153,4 +172,202 @@ mod tests {
 
         test_packet!(data, 5);
     }
+
+    #[test]
+    fn test_udp_init_packet() {
+        let data = UdpInitPacket { port: 1027 };
+
+        test_packet!(data, 2);
+    }
+
+    #[test]
+    fn test_hole_punch_packet() {
+        let buf = raw_header(14, 0).freeze();
+        let packet = Packet::from_bytes(buf.clone()).unwrap();
+
+        assert_eq!(packet.data, PacketData::HolePunch);
+        assert!(packet.trailing.is_empty());
+        assert_eq!(packet.to_bytes(), buf);
+    }
+
+    // region: Codec
+    fn moon_packet(id: i32) -> Packet {
+        let data = MoonPacket {
+            id,
+            is_grand: false,
+        };
+        data.into_packet(Uuid::new_v4())
+    }
+
+    fn raw_header(packet_id: u16, body_length: u16) -> BytesMut {
+        let mut buf = BytesMut::new();
+        Uuid::new_v4().write_bytes(&mut buf);
+        packet_id.write_bytes(&mut buf);
+        body_length.write_bytes(&mut buf);
+
+        buf
+    }
+
+    /// Feed bytes into the codec in chunks, collecting all decoded packets
+    fn decode_chunked(
+        codec: &mut PacketCodec,
+        bytes: &[u8],
+        chunk_size: usize,
+    ) -> Result<Vec<Packet>, CodecError> {
+        let mut buf = BytesMut::new();
+        let mut packets = vec![];
+
+        for chunk in bytes.chunks(chunk_size) {
+            buf.put(chunk);
+            while let Some(packet) = codec.decode(&mut buf)? {
+                packets.push(packet);
+            }
+        }
+
+        Ok(packets)
+    }
+
+    #[test]
+    fn test_codec_fragmented() {
+        let packets = [moon_packet(1), moon_packet(2), moon_packet(3)];
+        let bytes = packets
+            .iter()
+            .flat_map(Packet::to_bytes)
+            .collect::<Vec<_>>();
+
+        for chunk_size in [1, 3, 7, bytes.len()] {
+            let mut codec = PacketCodec::default();
+            let decoded = decode_chunked(&mut codec, &bytes, chunk_size).unwrap();
+
+            assert_eq!(decoded, packets);
+        }
+    }
+
+    #[test]
+    fn test_codec_incomplete_frame() {
+        let bytes = moon_packet(1).to_bytes();
+        let mut buf = BytesMut::from(&bytes[..bytes.len() - 1]);
+
+        let mut codec = PacketCodec::default();
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert_eq!(buf.len(), bytes.len() - 1);
+    }
+
+    #[test]
+    fn test_codec_oversized_strict() {
+        let body_length = MAX_BODY_LENGTH as u16 + 1;
+        let mut buf = raw_header(2, body_length);
+
+        let mut codec = PacketCodec::new(FrameMode::Strict);
+        let result = codec.decode(&mut buf);
+
+        assert!(
+            matches!(result, Err(CodecError::FrameTooLarge { body_length: len }) if len == body_length as usize)
+        );
+    }
+
+    #[test]
+    fn test_codec_oversized_lenient() {
+        let body_length = MAX_BODY_LENGTH as u16 * 2;
+        let mut bytes = raw_header(2, body_length);
+        bytes.put_bytes(0xFF, body_length as usize);
+
+        let packet = moon_packet(69);
+        bytes.put(packet.to_bytes());
+
+        for chunk_size in [1, 5, 100, bytes.len()] {
+            let mut codec = PacketCodec::new(FrameMode::Lenient);
+            let decoded = decode_chunked(&mut codec, &bytes, chunk_size).unwrap();
+
+            assert_eq!(decoded, std::slice::from_ref(&packet));
+        }
+    }
+
+    #[test]
+    fn test_codec_malformed() {
+        // Connect packet with an invalid connection type
+        let mut bytes = raw_header(6, 38);
+        bytes.put_u32_le(69);
+        bytes.put_bytes(0, 34);
+
+        let packet = moon_packet(420);
+        bytes.put(packet.to_bytes());
+
+        let mut strict = PacketCodec::new(FrameMode::Strict);
+        let result = decode_chunked(&mut strict, &bytes, 4);
+        assert!(matches!(
+            result,
+            Err(CodecError::InvalidPacket(ProtocolError::InvalidEnum { .. }))
+        ));
+
+        let mut lenient = PacketCodec::new(FrameMode::Lenient);
+        let decoded = decode_chunked(&mut lenient, &bytes, 4).unwrap();
+        assert_eq!(decoded, [packet]);
+    }
+
+    #[test]
+    fn test_short_read() {
+        let packet = moon_packet(420).to_bytes();
+
+        let truncated = packet.slice(..packet.len() - 1);
+        let result = Packet::from_bytes(truncated);
+        assert!(matches!(result, Err(ProtocolError::LengthMismatch { .. })));
+
+        let header = Packet::buf_size();
+        let result = PartialPacket::from_bytes(&mut &packet[..header - 1]);
+        assert!(matches!(result, Err(ProtocolError::ShortRead { .. })));
+
+        let result = MoonPacket::from_bytes(&mut &packet[header..header + 2]);
+        assert!(matches!(result, Err(ProtocolError::ShortRead { .. })));
+    }
+
+    #[test]
+    fn test_trailing_bytes() {
+        // Moon packet with extra fields from a newer client
+        let mut bytes = raw_header(9, 8);
+        bytes.put_i32_le(42);
+        bytes.put_u8(1);
+        bytes.put(&b"new"[..]);
+
+        let packet = Packet::from_bytes(bytes.clone().freeze()).unwrap();
+        assert_eq!(
+            packet.data,
+            PacketData::Moon(MoonPacket {
+                id: 42,
+                is_grand: true
+            })
+        );
+        assert_eq!(&packet.trailing[..], b"new");
+        assert_eq!(packet.to_bytes(), bytes);
+
+        // Body shorter than the known fields
+        let mut bytes = raw_header(9, 2);
+        bytes.put_u16_le(0);
+
+        let result = Packet::from_bytes(bytes.freeze());
+        assert!(matches!(result, Err(ProtocolError::ShortRead { .. })));
+    }
+
+    #[test]
+    fn test_codec_raw() {
+        let mut bytes = raw_header(42, 4);
+        bytes.put_u32_le(1337);
+
+        let mut codec = PacketCodec::default();
+        let decoded = decode_chunked(&mut codec, &bytes, 3).unwrap();
+
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(
+            decoded[0].data,
+            PacketData::Raw {
+                packet_id: 42,
+                body: bytes[Packet::buf_size()..].to_vec().into()
+            }
+        );
+
+        let mut encoded = BytesMut::new();
+        codec.encode(decoded[0].clone(), &mut encoded).unwrap();
+        assert_eq!(encoded, bytes);
+    }
+    // endregion
 }
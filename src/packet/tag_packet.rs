@@ -3,8 +3,36 @@ use smoo_derive::Packet;
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Packet)]
 #[packet("Tag")]
 pub struct TagPacket {
-    update_bits: u8,
-    is_it: bool,
-    seconds: u8,
-    minutes: u16,
+    pub update_bits: u8,
+    pub is_it: bool,
+    pub seconds: u8,
+    pub minutes: u16,
+}
+
+impl TagPacket {
+    /// `minutes` and `seconds` should be applied
+    pub const UPDATE_TIME: u8 = 1 << 0;
+
+    /// `is_it` should be applied
+    pub const UPDATE_STATE: u8 = 1 << 1;
+
+    #[inline]
+    pub fn time(minutes: u16, seconds: u8) -> Self {
+        Self {
+            update_bits: Self::UPDATE_TIME,
+            is_it: false,
+            seconds,
+            minutes,
+        }
+    }
+
+    #[inline]
+    pub fn state(is_it: bool) -> Self {
+        Self {
+            update_bits: Self::UPDATE_STATE,
+            is_it,
+            seconds: 0,
+            minutes: 0,
+        }
+    }
 }
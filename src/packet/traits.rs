@@ -1,7 +1,7 @@
 use bytes::{Buf, BytesMut};
-use color_eyre::Result;
 use uuid::Uuid;
 
+use super::error::Result;
 use super::header::{Packet, PacketData};
 
 pub trait PacketBytes
@@ -15,9 +15,6 @@ where
 pub trait IntoPacket: PacketBytes + Into<PacketData> {
     #[inline]
     fn into_packet(self, id: Uuid) -> Packet {
-        Packet {
-            id,
-            data: self.into(),
-        }
+        Packet::new(id, self.into())
     }
 }
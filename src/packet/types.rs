@@ -1,8 +1,8 @@
 use bytes::{Buf, BufMut, BytesMut};
-use color_eyre::Result;
 use glam::{Quat, Vec3};
 use uuid::Uuid;
 
+use super::error::{ensure_remaining, Result};
 use super::traits::PacketBytes;
 
 // region: Standard Types
@@ -17,6 +17,8 @@ impl PacketBytes for bool {
 
     #[inline]
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
+        ensure_remaining(buf, std::mem::size_of::<u8>(), "bool")?;
+
         let uint = buf.get_u8();
         Ok(uint == 1)
     }
@@ -31,6 +33,8 @@ impl<const N: usize> PacketBytes for [u8; N] {
 
     #[inline]
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
+        ensure_remaining(buf, N, "byte array")?;
+
         let mut dst = [0u8; N];
         buf.copy_to_slice(&mut dst);
 
@@ -50,18 +54,12 @@ impl<const N: usize> PacketBytes for [f32; N] {
 
     #[inline]
     fn from_bytes<T: bytes::Buf>(buf: &mut T) -> Result<Self> {
-        let fsize = std::mem::size_of::<f32>();
-        let bytes = std::mem::size_of::<Self>();
+        ensure_remaining(buf, std::mem::size_of::<Self>(), "float array")?;
 
-        let vec = buf
-            .copy_to_bytes(bytes)
-            .chunks(fsize)
-            .map(|mut chunk| chunk.get_f32_le())
-            .collect::<Vec<_>>();
-
-        let array = vec
-            .try_into()
-            .map_err(|_| color_eyre::eyre::eyre!("invalid vector of floats"))?;
+        let mut array = [0f32; N];
+        for f in array.iter_mut() {
+            *f = buf.get_f32_le();
+        }
 
         Ok(array)
     }
@@ -81,7 +79,8 @@ macro_rules! packet_bytes_num {
                 }
 
                 #[inline]
-                fn from_bytes<T: bytes::Buf>(buf: &mut T)-> color_eyre::Result<Self> {
+                fn from_bytes<T: bytes::Buf>(buf: &mut T) -> Result<Self> {
+                    ensure_remaining(buf, std::mem::size_of::<$type>(), stringify!($type))?;
                     Ok(buf.[<get_ $type>]())
                 }
             }
@@ -101,7 +100,8 @@ macro_rules! packet_bytes_num_le {
                 }
 
                 #[inline]
-                fn from_bytes<T: bytes::Buf>(buf: &mut T) -> color_eyre::Result<Self> {
+                fn from_bytes<T: bytes::Buf>(buf: &mut T) -> Result<Self> {
+                    ensure_remaining(buf, std::mem::size_of::<$type>(), stringify!($type))?;
                     Ok(buf.[<get_ $type _le>]())
                 }
             }
@@ -129,6 +129,8 @@ impl PacketBytes for Uuid {
 
     #[inline]
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
+        ensure_remaining(buf, 16, "uuid")?;
+
         let mut dst = [0u8; 16];
         buf.copy_to_slice(&mut dst);
 
@@ -149,6 +151,8 @@ impl PacketBytes for Vec3 {
 
     #[inline]
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
+        ensure_remaining(buf, std::mem::size_of::<f32>() * 3, "vec3")?;
+
         let vec3 = Self {
             x: buf.get_f32_le(),
             y: buf.get_f32_le(),
@@ -172,6 +176,8 @@ impl PacketBytes for Quat {
 
     #[inline]
     fn from_bytes<T: Buf>(buf: &mut T) -> Result<Self> {
+        ensure_remaining(buf, std::mem::size_of::<f32>() * 4, "quat")?;
+
         let quat = Quat::from_xyzw(
             buf.get_f32_le(),
             buf.get_f32_le(),
@@ -0,0 +1,8 @@
+use smoo_derive::Packet;
+
+/// Tells the client which port to send its hole punch packet to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Packet)]
+#[packet("UdpInit")]
+pub struct UdpInitPacket {
+    pub port: u16,
+}
@@ -1,51 +1,326 @@
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 
 use futures::SinkExt;
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+use tracing::{trace, warn};
 use uuid::Uuid;
 
-use crate::packet::{IntoPacket, Packet};
+use crate::config::SendQueueConfig;
+use crate::packet::{IntoPacket, Packet, PacketData};
+use crate::rate_limit::{PacketLimiter, RateCheck};
 use crate::server::Sink;
 
 pub struct Peer {
     pub id: Uuid,
     addr: SocketAddr,
-    sink: Sink,
+    queue: Arc<SendQueue>,
+    udp: Option<UdpEndpoint>,
+    limiter: PacketLimiter,
+}
+
+#[derive(Debug)]
+struct UdpEndpoint {
+    socket: Arc<UdpSocket>,
+    addr: SocketAddr,
 }
 
 impl Peer {
-    pub fn new(sink: Sink, addr: SocketAddr) -> Self {
+    pub fn new(
+        sink: Sink,
+        addr: SocketAddr,
+        limits: SendQueueConfig,
+        limiter: PacketLimiter,
+    ) -> Self {
+        let queue = Arc::new(SendQueue::new(limits));
+        tokio::spawn(write_loop(queue.clone(), sink));
+
         Self {
             id: Uuid::nil(),
             addr,
-            sink,
+            queue,
+            udp: None,
+            limiter,
         }
     }
 
     #[inline]
-    pub async fn send(&mut self, packet: Packet) {
-        let _ = self.sink.send(packet).await;
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
     }
 
-    pub async fn send_nil_uuid<T: IntoPacket>(&mut self, packet: T) {
-        let data = packet.into();
-        let packet = Packet {
-            id: Uuid::nil(),
-            data,
-        };
+    /// Send movement packets to `addr` over UDP from now on
+    ///
+    /// Only addresses with the same IP as the TCP connection are accepted.
+    pub fn bind_udp(&mut self, socket: Arc<UdpSocket>, addr: SocketAddr) -> bool {
+        if addr.ip() != self.addr.ip() {
+            return false;
+        }
+
+        self.udp = Some(UdpEndpoint { socket, addr });
+        true
+    }
+
+    #[inline]
+    pub fn udp_addr(&self) -> Option<SocketAddr> {
+        self.udp.as_ref().map(|udp| udp.addr)
+    }
 
-        let _ = self.sink.send(packet).await;
+    /// Start over with new rate limits, eg: after the config changed
+    #[inline]
+    pub fn set_limiter(&mut self, limiter: PacketLimiter) {
+        self.limiter = limiter;
     }
 
+    /// Count a packet received from this peer against its rate limits
     #[inline]
-    pub async fn disconnect(&mut self) {
-        // TODO: Handle error
-        let _ = self.sink.close().await;
+    pub fn check_rate(&mut self, data: &PacketData) -> RateCheck {
+        self.limiter.check(data.name())
+    }
+
+    pub fn send(&self, packet: Packet) {
+        if let (Some(udp), PacketData::Player(_) | PacketData::Cap(_)) = (&self.udp, &packet.data) {
+            match udp.socket.try_send_to(&packet.to_bytes(), udp.addr) {
+                Ok(_) => return,
+                Err(error) => trace!(id = %self.id, %error, "udp send failed, falling back to tcp"),
+            }
+        }
+
+        if let Err(len) = self.queue.push(packet) {
+            warn!(id = %self.id, addr = %self.addr, len, "send queue full, disconnecting");
+            self.queue.abort();
+        }
+    }
+
+    pub fn send_nil_uuid<T: IntoPacket>(&self, packet: T) {
+        let packet = packet.into_packet(Uuid::nil());
+        self.send(packet);
+    }
+
+    /// Stop sending packets and close the connection
+    ///
+    /// Packets that are already queued are still sent before the socket closes.
+    #[inline]
+    pub fn disconnect(&self) {
+        self.queue.close();
+    }
+
+    /// Token that is cancelled once the peer has been disconnected
+    #[inline]
+    pub fn closed(&self) -> CancellationToken {
+        self.queue.closed.clone()
+    }
+}
+
+impl Drop for Peer {
+    #[inline]
+    fn drop(&mut self) {
+        self.queue.close();
     }
 }
 
 impl Debug for Peer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Peer").field("addr", &self.addr).finish()
+        f.debug_struct("Peer")
+            .field("id", &self.id)
+            .field("addr", &self.addr)
+            .field("udp", &self.udp_addr())
+            .finish()
+    }
+}
+
+// region: Send Queue
+struct SendQueue {
+    packets: Mutex<VecDeque<Packet>>,
+    limits: SendQueueConfig,
+
+    notify: Notify,
+    closed: CancellationToken,
+}
+
+impl SendQueue {
+    fn new(limits: SendQueueConfig) -> Self {
+        Self {
+            packets: Mutex::default(),
+            limits,
+
+            notify: Notify::new(),
+            closed: CancellationToken::new(),
+        }
+    }
+
+    /// Queue a packet, returns the queue length if the queue is full
+    fn push(&self, packet: Packet) -> Result<(), usize> {
+        if self.closed.is_cancelled() {
+            return Ok(());
+        }
+
+        let mut packets = self.packets.lock().unwrap();
+
+        // Once the peer falls behind, only keep the latest player packet from each sender
+        if packets.len() >= self.limits.coalesce_len {
+            if let PacketData::Player(_) = packet.data {
+                packets.retain(|queued| {
+                    !(queued.id == packet.id && matches!(queued.data, PacketData::Player(_)))
+                });
+            }
+        }
+
+        if packets.len() >= self.limits.max_len {
+            return Err(packets.len());
+        }
+
+        packets.push_back(packet);
+        self.notify.notify_one();
+
+        Ok(())
+    }
+
+    async fn pop(&self) -> Option<Packet> {
+        loop {
+            if let Some(packet) = self.packets.lock().unwrap().pop_front() {
+                return Some(packet);
+            }
+
+            if self.closed.is_cancelled() {
+                return None;
+            }
+
+            tokio::select! {
+                _ = self.notify.notified() => (),
+                _ = self.closed.cancelled() => (),
+            }
+        }
+    }
+
+    #[inline]
+    fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Close without sending any queued packets
+    #[inline]
+    fn abort(&self) {
+        self.packets.lock().unwrap().clear();
+        self.close();
+    }
+}
+
+async fn write_loop(queue: Arc<SendQueue>, mut sink: Sink) {
+    while let Some(packet) = queue.pop().await {
+        if sink.send(packet).await.is_err() {
+            break;
+        }
+    }
+
+    queue.close();
+    let _ = sink.close().await;
+}
+// endregion
+
+#[cfg(test)]
+mod tests {
+    use glam::{Quat, Vec3};
+
+    use super::*;
+    use crate::packet::{MoonPacket, PlayerPacket};
+
+    fn player_packet(id: Uuid, act: i16) -> Packet {
+        let data = PlayerPacket {
+            position: Vec3::ZERO,
+            quaternion: Quat::IDENTITY,
+            animation_blend_weights: [0.0; 6],
+            act,
+            subact: 0,
+        };
+        data.into_packet(id)
+    }
+
+    fn queued(queue: &SendQueue) -> Vec<Packet> {
+        queue.packets.lock().unwrap().iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_coalesce_player_packets() {
+        let queue = SendQueue::new(SendQueueConfig {
+            coalesce_len: 4,
+            max_len: 16,
+        });
+
+        let (sender, other) = (Uuid::new_v4(), Uuid::new_v4());
+        for id in 0..4 {
+            queue
+                .push(
+                    MoonPacket {
+                        id,
+                        is_grand: false,
+                    }
+                    .into_packet(other),
+                )
+                .unwrap();
+        }
+
+        for act in 0..10 {
+            queue.push(player_packet(sender, act)).unwrap();
+        }
+        queue.push(player_packet(other, 0)).unwrap();
+
+        let packets = queued(&queue);
+        let player_packets: Vec<_> = packets
+            .iter()
+            .filter(|packet| packet.id == sender)
+            .collect();
+
+        assert_eq!(player_packets.len(), 1);
+        assert!(matches!(player_packets[0].data, PacketData::Player(data) if data.act == 9));
+
+        // Other packets are never dropped
+        let moons = packets
+            .iter()
+            .filter(|packet| matches!(packet.data, PacketData::Moon(_)))
+            .count();
+        assert_eq!(moons, 4);
+        assert_eq!(packets.len(), 6);
+    }
+
+    #[test]
+    fn test_queue_full() {
+        let queue = SendQueue::new(SendQueueConfig {
+            coalesce_len: 2,
+            max_len: 2,
+        });
+
+        let id = Uuid::new_v4();
+        queue
+            .push(
+                MoonPacket {
+                    id: 1,
+                    is_grand: false,
+                }
+                .into_packet(id),
+            )
+            .unwrap();
+        queue
+            .push(
+                MoonPacket {
+                    id: 2,
+                    is_grand: false,
+                }
+                .into_packet(id),
+            )
+            .unwrap();
+        let result = queue.push(
+            MoonPacket {
+                id: 3,
+                is_grand: false,
+            }
+            .into_packet(id),
+        );
+
+        assert_eq!(result, Err(2));
     }
 }
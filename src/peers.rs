@@ -1,19 +1,23 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
 
-use color_eyre::eyre::eyre;
-use color_eyre::Result;
-use futures::future::join_all;
-use tokio::sync::RwLock;
+use dashmap::DashMap;
+use tokio::net::UdpSocket;
 use tracing::info;
 use uuid::Uuid;
 
-use crate::packet::Packet;
+use crate::config::FloodConfig;
+use crate::packet::{Packet, PacketData};
 use crate::peer::Peer;
-use crate::players::Players;
+use crate::rate_limit::{PacketLimiter, RateCheck};
 
+/// Sharded map of connected peers
+///
+/// Like [`Players`](crate::players::Players), references must never be held across an `.await`.
 #[derive(Debug, Default)]
 pub struct Peers {
-    map: HashMap<Uuid, Peer>,
+    map: DashMap<Uuid, Peer>,
 }
 
 impl Peers {
@@ -23,65 +27,111 @@ impl Peers {
     }
 
     #[inline]
-    pub fn get_mut(&mut self, id: &Uuid) -> Result<&mut Peer> {
-        self.map
-            .get_mut(id)
-            .ok_or_else(|| eyre!("peer should exist in the map"))
+    pub fn keys(&self) -> Vec<Uuid> {
+        self.map.iter().map(|entry| *entry.key()).collect()
     }
 
     #[inline]
-    pub fn keys(&self) -> impl Iterator<Item = Uuid> + '_ {
-        self.map.keys().copied()
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.map.contains_key(id)
     }
 
-    #[inline]
-    pub fn insert(&mut self, id: Uuid, peer: Peer) -> Option<Peer> {
-        self.map.insert(id, peer)
+    /// Insert a peer, kicking any older connection with the same id
+    pub fn insert(&self, id: Uuid, peer: Peer) {
+        if let Some(old) = self.map.insert(id, peer) {
+            info!(%id, addr = %old.addr(), "kicking older connection");
+            old.disconnect();
+        }
     }
 
-    pub async fn remove(&mut self, id: &Uuid, players: &RwLock<Players>) -> Option<Peer> {
-        let peer = self.map.remove(id);
-        let peer = match peer {
-            Some(mut peer) => {
-                peer.disconnect().await;
-                Some(peer)
+    /// Remove and disconnect a peer, unless it was replaced by a newer
+    /// connection from a different address
+    pub fn remove(&self, id: &Uuid, addr: SocketAddr) -> Option<Peer> {
+        let (_, peer) = self.map.remove_if(id, |_, peer| peer.addr() == addr)?;
+        peer.disconnect();
+
+        Some(peer)
+    }
+
+    /// Disconnect a peer, the connection task cleans up after it
+    pub fn disconnect(&self, id: &Uuid) -> bool {
+        match self.map.get(id) {
+            Some(peer) => {
+                peer.disconnect();
+                true
             }
 
-            None => peer,
-        };
+            None => false,
+        }
+    }
+
+    /// Disconnect every peer, see [`Peers::disconnect`]
+    pub fn disconnect_all(&self) {
+        self.map.iter().for_each(|peer| peer.disconnect());
+    }
 
-        let mut players = players.write().await;
-        if let Some(player) = players.remove(id) {
-            info!("{player} disconnected");
-        };
+    #[inline]
+    pub fn addrs(&self) -> Vec<(Uuid, SocketAddr)> {
+        self.map
+            .iter()
+            .map(|peer| (*peer.key(), peer.addr()))
+            .collect()
+    }
 
-        peer
+    /// Bind a UDP address to a peer, see [`Peer::bind_udp`]
+    pub fn bind_udp(&self, id: &Uuid, socket: Arc<UdpSocket>, addr: SocketAddr) -> bool {
+        match self.map.get_mut(id) {
+            Some(mut peer) => peer.bind_udp(socket, addr),
+            None => false,
+        }
     }
 
-    pub async fn broadcast(&mut self, packet: Packet) {
-        let sender = packet.id;
-        let jobs =
-            self.map
-                .iter_mut()
-                .filter(|(id, _)| **id != sender)
-                .map(|(_, peer)| async move {
-                    peer.send(packet).await;
-                });
+    /// Replace the packet rate limits of every peer, eg: after the config changed
+    pub fn set_flood_limits(&self, config: &FloodConfig) {
+        self.map
+            .iter_mut()
+            .for_each(|mut peer| peer.set_limiter(PacketLimiter::new(config)));
+    }
+
+    /// See [`Peer::check_rate`]
+    pub fn check_rate(&self, id: &Uuid, data: &PacketData) -> RateCheck {
+        match self.map.get_mut(id) {
+            Some(mut peer) => peer.check_rate(data),
+            None => RateCheck::Allow,
+        }
+    }
 
-        join_all(jobs).await;
+    #[inline]
+    pub fn udp_addr(&self, id: &Uuid) -> Option<SocketAddr> {
+        self.map.get(id).and_then(|peer| peer.udp_addr())
+    }
+
+    #[inline]
+    pub fn send(&self, id: &Uuid, packet: Packet) {
+        if let Some(peer) = self.map.get(id) {
+            peer.send(packet);
+        }
     }
 
-    pub async fn broadcast_some(&mut self, packet: Packet, players: HashSet<Uuid>) {
+    pub fn broadcast(&self, packet: Packet) {
         let sender = packet.id;
-        let jobs = self
-            .map
-            .iter_mut()
-            .filter(|(id, _)| **id != sender)
-            .filter(|(id, _)| players.contains(id))
-            .map(|(_, peer)| async move {
-                peer.send(packet).await;
-            });
+        self.map
+            .iter()
+            .filter(|peer| *peer.key() != sender)
+            .for_each(|peer| peer.send(packet.clone()));
+    }
 
-        join_all(jobs).await;
+    /// Send a packet to every peer, including the sender
+    pub fn send_all(&self, packet: Packet) {
+        self.map.iter().for_each(|peer| peer.send(packet.clone()));
+    }
+
+    pub fn broadcast_some(&self, packet: Packet, players: &HashSet<Uuid>) {
+        let sender = packet.id;
+        self.map
+            .iter()
+            .filter(|peer| *peer.key() != sender)
+            .filter(|peer| players.contains(peer.key()))
+            .for_each(|peer| peer.send(packet.clone()));
     }
 }
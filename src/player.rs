@@ -1,6 +1,9 @@
+use std::collections::HashSet;
 use std::fmt::Display;
+use std::time::Instant;
 
 use color_eyre::{Report, Result};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::moons::MoonMap;
@@ -17,6 +20,13 @@ pub struct Player {
 
     pub last_pos: Option<PlayerPacket>,
     pub last_game: Option<GamePacket>,
+
+    /// Kingdoms this player has entered, eg: `CapWorld`
+    pub kingdoms: HashSet<String>,
+    pub last_kingdom: Option<String>,
+
+    /// Start of the current session
+    pub connected_at: Instant,
 }
 
 impl Player {
@@ -31,6 +41,11 @@ impl Player {
 
             last_pos: None,
             last_game: None,
+
+            kingdoms: HashSet::new(),
+            last_kingdom: None,
+
+            connected_at: Instant::now(),
         }
     }
 
@@ -40,6 +55,17 @@ impl Player {
             .and_then(|x| x.stage.try_as_str().ok())
     }
 
+    /// Update the current stage, tracking which kingdoms have been visited
+    pub fn set_game(&mut self, data: GamePacket) {
+        self.last_game = Some(data);
+
+        let kingdom = self.stage().and_then(kingdom_name).map(ToOwned::to_owned);
+        if let Some(kingdom) = kingdom {
+            self.kingdoms.insert(kingdom.clone());
+            self.last_kingdom = Some(kingdom);
+        }
+    }
+
     #[inline]
     pub fn set_costume(&mut self, data: CostumePacket) -> Result<()> {
         self.costume = Some(data.try_into()?);
@@ -48,6 +74,14 @@ impl Player {
     }
 }
 
+/// Kingdom that a stage belongs to, eg: `CapWorldHomeStage` -> `CapWorld`
+///
+/// Sub-areas don't include their kingdom in their name, so return `None`
+#[inline]
+pub fn kingdom_name(stage: &str) -> Option<&str> {
+    stage.find("World").map(|idx| &stage[..idx + "World".len()])
+}
+
 impl Display for Player {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}/{}", self.name, self.id)
@@ -56,7 +90,7 @@ impl Display for Player {
 // endregion
 
 // region: Costume
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Costume {
     pub body: String,
     pub cap: String,
@@ -77,7 +111,7 @@ impl TryFrom<CostumePacket> for Costume {
 
     fn try_from(packet: CostumePacket) -> Result<Self, Self::Error> {
         let body = packet.body.try_into()?;
-        let cap = packet.body.try_into()?;
+        let cap = packet.cap.try_into()?;
 
         Ok(Self { body, cap })
     }
@@ -88,9 +122,35 @@ impl TryFrom<Costume> for CostumePacket {
 
     fn try_from(costume: Costume) -> Result<Self, Self::Error> {
         let body = costume.body.parse()?;
-        let cap = costume.body.parse()?;
+        let cap = costume.cap.parse()?;
 
         Ok(Self { body, cap })
     }
 }
 // endregion
+
+#[cfg(test)]
+mod tests {
+    use super::kingdom_name;
+
+    #[test]
+    fn test_kingdom_name() {
+        assert_eq!(kingdom_name("CapWorldHomeStage"), Some("CapWorld"));
+        assert_eq!(kingdom_name("CapWorldTowerStage"), Some("CapWorld"));
+        assert_eq!(
+            kingdom_name("Special1WorldHomeStage"),
+            Some("Special1World")
+        );
+        assert_eq!(
+            kingdom_name("ForestWorldCloudBonusExStage"),
+            Some("ForestWorld")
+        );
+    }
+
+    #[test]
+    fn test_kingdom_name_sub_area() {
+        assert_eq!(kingdom_name("PoisonWaveExStage"), None);
+        assert_eq!(kingdom_name("FrogSearchExStage"), None);
+        assert_eq!(kingdom_name(""), None);
+    }
+}
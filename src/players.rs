@@ -1,48 +1,170 @@
-use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
 use color_eyre::eyre::eyre;
 use color_eyre::Result;
+use dashmap::mapref::entry::Entry;
+use dashmap::mapref::multiple::{RefMulti, RefMutMulti};
+use dashmap::mapref::one::{Ref, RefMut};
+use dashmap::DashMap;
+use tokio::time::{Duration, Instant};
+use tracing::info;
 use uuid::Uuid;
 
 use crate::player::Player;
 
+/// Sharded map of connected players
+///
+/// References lock a shard of the map, so they must never be held across an
+/// `.await` or while accessing another entry (or the peer map).
 #[derive(Debug, Default)]
 pub struct Players {
-    map: HashMap<Uuid, Player>,
+    map: DashMap<Uuid, Player>,
+
+    /// Recently disconnected players, kept so they can reconnect
+    away: DashMap<Uuid, (Player, Instant)>,
 }
 
 impl Players {
     #[inline]
-    pub fn get(&self, id: &Uuid) -> Result<&Player> {
+    pub fn get(&self, id: &Uuid) -> Result<Ref<'_, Uuid, Player>> {
         self.map
             .get(id)
             .ok_or_else(|| eyre!("player should exist in the map"))
     }
 
     #[inline]
-    pub fn get_mut(&mut self, id: &Uuid) -> Result<&mut Player> {
+    pub fn get_mut(&self, id: &Uuid) -> Result<RefMut<'_, Uuid, Player>> {
         self.map
             .get_mut(id)
             .ok_or_else(|| eyre!("player should exist in the map"))
     }
 
     #[inline]
-    pub fn insert(&mut self, id: Uuid, player: Player) -> Option<Player> {
+    pub fn insert(&self, id: Uuid, player: Player) -> Option<Player> {
+        self.away.remove(&id);
         self.map.insert(id, player)
     }
 
-    #[inline]
-    pub fn remove(&mut self, id: &Uuid) -> Option<Player> {
-        self.map.remove(id)
+    /// Move a player out of the map, keeping its state around for `grace`
+    pub fn disconnect(&self, id: &Uuid, grace: Duration) {
+        self.away.retain(|_, (_, at)| at.elapsed() < grace);
+
+        if let Some((_, player)) = self.map.remove(id) {
+            info!("{player} disconnected");
+
+            if !grace.is_zero() {
+                self.away.insert(*id, (player, Instant::now()));
+            }
+        }
+    }
+
+    /// Restore a player that disconnected less than `grace` ago
+    ///
+    /// Returns `true` if the player is in the map afterwards.
+    pub fn restore(&self, id: &Uuid, grace: Duration) -> bool {
+        if self.map.contains_key(id) {
+            return true;
+        }
+
+        match self.away.remove(id) {
+            Some((_, (mut player, at))) if at.elapsed() < grace => {
+                player.connected_at = std::time::Instant::now();
+                self.map.insert(*id, player);
+                true
+            }
+
+            _ => false,
+        }
     }
 
     #[inline]
-    pub fn all_players(&self) -> impl Iterator<Item = &Player> + '_ {
-        self.map.values()
+    pub fn all_players(&self) -> impl Iterator<Item = RefMulti<'_, Uuid, Player>> + '_ {
+        self.map.iter()
     }
 
     #[inline]
-    pub fn all_players_mut(&mut self) -> impl Iterator<Item = &mut Player> + '_ {
-        self.map.values_mut()
+    pub fn all_players_mut(&self) -> impl Iterator<Item = RefMutMulti<'_, Uuid, Player>> + '_ {
+        self.map.iter_mut()
+    }
+}
+
+/// Nicknames of joining and connected players, compared case-insensitively
+///
+/// A name is reserved before the player is added, so two players joining at
+/// the same time can't both take it.
+#[derive(Debug, Default)]
+pub struct Nicknames {
+    map: Arc<DashMap<String, (Uuid, u64)>>,
+    next_ticket: AtomicU64,
+}
+
+impl Nicknames {
+    /// Reserve `name` for `id` until the reservation is dropped
+    ///
+    /// Returns `None` if another player has the name, the same player
+    /// takes it over, eg: when replacing an older connection.
+    pub fn reserve(&self, id: Uuid, name: &str) -> Option<NameReservation> {
+        let key = name.to_lowercase();
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+
+        match self.map.entry(key.clone()) {
+            Entry::Occupied(entry) if entry.get().0 != id => return None,
+            entry => {
+                entry.insert((id, ticket));
+            }
+        }
+
+        Some(NameReservation {
+            key,
+            ticket,
+            map: self.map.clone(),
+        })
+    }
+}
+
+/// Releases a nickname when dropped, unless a newer connection took it over
+#[derive(Debug)]
+pub struct NameReservation {
+    key: String,
+    ticket: u64,
+    map: Arc<DashMap<String, (Uuid, u64)>>,
+}
+
+impl Drop for NameReservation {
+    fn drop(&mut self) {
+        self.map
+            .remove_if(&self.key, |_, (_, ticket)| *ticket == self.ticket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve_nickname() {
+        let nicknames = Nicknames::default();
+        let (mario, luigi) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let reservation = nicknames.reserve(mario, "Mario").unwrap();
+        assert!(nicknames.reserve(luigi, "mario").is_none());
+        assert!(nicknames.reserve(luigi, "Luigi").is_some());
+
+        drop(reservation);
+        assert!(nicknames.reserve(luigi, "MARIO").is_some());
+    }
+
+    #[test]
+    fn test_reserve_nickname_replaced() {
+        let nicknames = Nicknames::default();
+        let (mario, luigi) = (Uuid::new_v4(), Uuid::new_v4());
+
+        // A newer connection from the same player keeps the name after the old one closes
+        let old = nicknames.reserve(mario, "Mario").unwrap();
+        let _new = nicknames.reserve(mario, "Mario").unwrap();
+        drop(old);
+
+        assert!(nicknames.reserve(luigi, "Mario").is_none());
     }
 }
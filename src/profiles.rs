@@ -0,0 +1,194 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use color_eyre::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::config::SharedConfig;
+use crate::moons::MoonMap;
+use crate::player::{Costume, Player};
+
+// region: Profile
+/// Everything known about a player across sessions
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    pub id: Uuid,
+    pub name: String,
+
+    /// Every nickname used by this player, oldest first
+    #[serde(default)]
+    pub names: Vec<String>,
+
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+
+    /// Total play time in seconds
+    #[serde(default)]
+    pub play_time: u64,
+
+    /// Moons the player has, collected by them or synced from others
+    #[serde(default)]
+    pub moons: MoonMap,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+
+    // Tables must come after values when serialized as TOML
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub costume: Option<Costume>,
+}
+
+impl Profile {
+    #[inline]
+    fn new(player: &Player) -> Self {
+        let now = Utc::now();
+
+        Self {
+            id: player.id,
+            name: player.name.clone(),
+            names: vec![player.name.clone()],
+            first_seen: now,
+            last_seen: now,
+            play_time: 0,
+            moons: MoonMap::default(),
+            stage: None,
+            costume: None,
+        }
+    }
+
+    /// Copy the current state of a connected player
+    fn update(&mut self, player: &Player) {
+        if !self.names.contains(&player.name) {
+            self.names.push(player.name.clone());
+        }
+
+        self.name = player.name.clone();
+        self.last_seen = Utc::now();
+        self.moons.extend(&player.moons);
+
+        if let Some(stage) = player.stage() {
+            self.stage = Some(stage.to_owned());
+        }
+
+        if let Some(costume) = &player.costume {
+            self.costume = Some(costume.clone());
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let minutes = self.play_time / 60;
+        write!(
+            f,
+            "{}/{} last seen {}, played {}h {}m",
+            self.name,
+            self.id,
+            self.last_seen.format("%Y-%m-%d %H:%M:%S UTC"),
+            minutes / 60,
+            minutes % 60
+        )
+    }
+}
+
+fn serialize_profiles<S: Serializer>(
+    map: &BTreeMap<Uuid, Profile>,
+    ser: S,
+) -> Result<S::Ok, S::Error> {
+    ser.collect_seq(map.values())
+}
+
+fn deserialize_profiles<'de, D: Deserializer<'de>>(
+    de: D,
+) -> Result<BTreeMap<Uuid, Profile>, D::Error> {
+    let stored = Vec::<Profile>::deserialize(de)?;
+    let map = stored
+        .into_iter()
+        .map(|profile| (profile.id, profile))
+        .collect();
+
+    Ok(map)
+}
+// endregion
+
+// region: Profiles
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Profiles {
+    #[serde(
+        rename = "profiles",
+        serialize_with = "serialize_profiles",
+        deserialize_with = "deserialize_profiles"
+    )]
+    map: BTreeMap<Uuid, Profile>,
+
+    #[serde(skip)]
+    config: SharedConfig,
+}
+
+impl Profiles {
+    /// Record a player joining
+    pub fn connect(&mut self, player: &Player) {
+        self.map
+            .entry(player.id)
+            .or_insert_with(|| Profile::new(player))
+            .update(player);
+    }
+
+    /// Record a player leaving, adding the length of their session to their play time
+    pub fn disconnect(&mut self, player: &Player) {
+        let profile = self
+            .map
+            .entry(player.id)
+            .or_insert_with(|| Profile::new(player));
+
+        profile.update(player);
+        profile.play_time += player.connected_at.elapsed().as_secs();
+    }
+
+    #[inline]
+    pub fn get(&self, id: &Uuid) -> Option<&Profile> {
+        self.map.get(id)
+    }
+
+    #[inline]
+    pub fn all_profiles(&self) -> impl Iterator<Item = &Profile> + '_ {
+        self.map.values()
+    }
+
+    // region: Persistence
+    pub async fn load(config: SharedConfig) -> Result<Self> {
+        let mut profiles: Self = {
+            let cfg = config.read().await;
+            let path = &cfg.profiles.persist_file;
+
+            if cfg.profiles.persist && path.exists() {
+                let body = fs::read(path).await?;
+                toml::from_slice(&body)?
+            } else {
+                Profiles::default()
+            }
+        };
+
+        profiles.config = config;
+        profiles.save().await?;
+
+        Ok(profiles)
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        let cfg = self.config.read().await;
+        let path = &cfg.profiles.persist_file;
+
+        if cfg.profiles.persist {
+            let body = toml::to_string_pretty(&self)?;
+            fs::write(path, &body).await?;
+        }
+
+        Ok(())
+    }
+    // endregion
+}
+// endregion
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+use crate::config::FloodConfig;
+
+/// Token bucket that refills continuously up to its capacity
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Full bucket holding `capacity` tokens, refilled by `per_second` tokens every second
+    #[inline]
+    pub fn new(capacity: u32, per_second: f64) -> Self {
+        Self {
+            capacity: f64::from(capacity),
+            per_second,
+
+            tokens: f64::from(capacity),
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+
+        self.tokens = elapsed
+            .mul_add(self.per_second, self.tokens)
+            .min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Take a token, returns `false` if the bucket is empty
+    pub fn try_take(&mut self) -> bool {
+        self.refill();
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+
+    #[inline]
+    pub fn is_empty(&mut self) -> bool {
+        self.refill();
+        self.tokens < 1.0
+    }
+
+    #[inline]
+    pub fn is_full(&mut self) -> bool {
+        self.refill();
+        self.tokens >= self.capacity
+    }
+}
+
+/// Calls to [`KeyedLimiter::try_take`] between removing refilled buckets
+const PRUNE_INTERVAL: u32 = 256;
+
+/// Token buckets keyed by eg: IP address, created full on first use
+#[derive(Debug)]
+pub struct KeyedLimiter<K: Eq + Hash> {
+    capacity: u32,
+    per_second: f64,
+
+    buckets: DashMap<K, TokenBucket>,
+    calls: AtomicU32,
+}
+
+impl<K: Eq + Hash> KeyedLimiter<K> {
+    #[inline]
+    pub fn new(capacity: u32, per_second: f64) -> Self {
+        Self {
+            capacity,
+            per_second,
+
+            buckets: DashMap::new(),
+            calls: AtomicU32::new(0),
+        }
+    }
+
+    /// Take a token for `key`, returns `false` if its bucket is empty
+    pub fn try_take(&self, key: K) -> bool {
+        // Pruning scans every bucket, so it isn't done on every call
+        if self.calls.fetch_add(1, Ordering::Relaxed) % PRUNE_INTERVAL == PRUNE_INTERVAL - 1 {
+            self.prune();
+        }
+
+        self.buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(self.capacity, self.per_second))
+            .try_take()
+    }
+
+    /// Whether `key` has run out of tokens, without taking one
+    #[inline]
+    pub fn is_limited(&self, key: &K) -> bool {
+        match self.buckets.get_mut(key) {
+            Some(mut bucket) => bucket.is_empty(),
+            None => false,
+        }
+    }
+
+    /// Remove buckets that have refilled, they're the same as new ones
+    fn prune(&self) {
+        self.buckets.retain(|_, bucket| !bucket.is_full());
+    }
+}
+
+/// Open connections per IP
+#[derive(Debug, Default)]
+pub struct ConnectionCounter {
+    counts: Arc<DashMap<IpAddr, usize>>,
+    closed: Arc<Notify>,
+}
+
+impl ConnectionCounter {
+    /// Count a connection from `ip`, returns `None` if it already has `max` open connections
+    pub fn try_add(&self, ip: IpAddr, max: usize) -> Option<ConnectionGuard> {
+        // Refused IPs must not leave a zero count behind, it would keep `wait_empty` waiting
+        match self.counts.entry(ip) {
+            Entry::Occupied(mut count) if *count.get() < max => *count.get_mut() += 1,
+            Entry::Vacant(count) if max > 0 => {
+                count.insert(1);
+            }
+            _ => return None,
+        }
+
+        Some(ConnectionGuard {
+            ip,
+            counts: self.counts.clone(),
+            closed: self.closed.clone(),
+        })
+    }
+
+    /// Wait until every counted connection has been closed
+    pub async fn wait_empty(&self) {
+        loop {
+            // Register before checking, so a close in between isn't missed
+            let closed = self.closed.notified();
+            if self.counts.is_empty() {
+                return;
+            }
+
+            closed.await;
+        }
+    }
+}
+
+/// Stops counting a connection when dropped
+#[derive(Debug)]
+pub struct ConnectionGuard {
+    ip: IpAddr,
+    counts: Arc<DashMap<IpAddr, usize>>,
+    closed: Arc<Notify>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(mut count) = self.counts.get_mut(&self.ip) {
+            *count -= 1;
+        }
+
+        self.counts.remove_if(&self.ip, |_, count| *count == 0);
+        self.closed.notify_waiters();
+    }
+}
+
+/// Outcome of [`PacketLimiter::check`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateCheck {
+    Allow,
+    Drop,
+
+    /// Too many packets were dropped, the sender should be disconnected
+    Flood,
+}
+
+/// Limits for packets received from one player, by packet type
+#[derive(Debug, Default)]
+pub struct PacketLimiter {
+    buckets: HashMap<String, TokenBucket>,
+    dropped: Option<TokenBucket>,
+}
+
+impl PacketLimiter {
+    pub fn new(config: &FloodConfig) -> Self {
+        if !config.enabled {
+            return Self::default();
+        }
+
+        let buckets = config
+            .limits
+            .iter()
+            .map(|(name, limit)| (name.clone(), TokenBucket::new(limit.burst, limit.rate)))
+            .collect();
+
+        Self {
+            buckets,
+            dropped: Some(TokenBucket::new(config.max_dropped, 1.0)),
+        }
+    }
+
+    /// Count a packet of type `name`, see [`PacketData::name`](crate::packet::PacketData::name)
+    pub fn check(&mut self, name: &str) -> RateCheck {
+        let bucket = match self.buckets.get_mut(name) {
+            Some(bucket) => bucket,
+            None => return RateCheck::Allow,
+        };
+
+        if bucket.try_take() {
+            return RateCheck::Allow;
+        }
+
+        match self.dropped.as_mut().map(TokenBucket::try_take) {
+            Some(false) => RateCheck::Flood,
+            _ => RateCheck::Drop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::PacketLimit;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([127, 0, 0, last])
+    }
+
+    fn flood_config(burst: u32, rate: f64, max_dropped: u32) -> FloodConfig {
+        FloodConfig {
+            max_dropped,
+            limits: [("player".to_owned(), PacketLimit { burst, rate })].into(),
+            ..FloodConfig::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_burst() {
+        let mut bucket = TokenBucket::new(3, 1.0);
+        assert!(bucket.is_full());
+
+        for _ in 0..3 {
+            assert!(bucket.try_take());
+        }
+
+        assert!(bucket.is_empty());
+        assert!(!bucket.try_take());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_bucket_refill() {
+        let mut bucket = TokenBucket::new(2, 2.0);
+        assert!(bucket.try_take() && bucket.try_take());
+        assert!(!bucket.try_take());
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+
+        // Never refills past its capacity
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(bucket.is_full());
+        assert!(bucket.try_take() && bucket.try_take());
+        assert!(!bucket.try_take());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_packet_limiter_flood() {
+        let mut limiter = PacketLimiter::new(&flood_config(2, 1.0, 3));
+        assert_eq!(limiter.check("player"), RateCheck::Allow);
+        assert_eq!(limiter.check("player"), RateCheck::Allow);
+
+        // Packets over the limit are dropped until too many were dropped
+        for _ in 0..3 {
+            assert_eq!(limiter.check("player"), RateCheck::Drop);
+        }
+        assert_eq!(limiter.check("player"), RateCheck::Flood);
+
+        // Packets without a limit are always allowed
+        assert_eq!(limiter.check("moon"), RateCheck::Allow);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(limiter.check("player"), RateCheck::Allow);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_packet_limiter_disabled() {
+        let config = FloodConfig {
+            enabled: false,
+            ..flood_config(1, 1.0, 1)
+        };
+
+        let mut limiter = PacketLimiter::new(&config);
+        for _ in 0..100 {
+            assert_eq!(limiter.check("player"), RateCheck::Allow);
+        }
+    }
+
+    #[test]
+    fn test_connection_cap() {
+        let counter = ConnectionCounter::default();
+        let first = counter.try_add(ip(1), 2).unwrap();
+        let _second = counter.try_add(ip(1), 2).unwrap();
+        assert!(counter.try_add(ip(1), 2).is_none());
+
+        // Other IPs have their own count
+        assert!(counter.try_add(ip(2), 2).is_some());
+
+        drop(first);
+        assert!(counter.try_add(ip(1), 2).is_some());
+    }
+
+    #[test]
+    fn test_connection_guard_drop() {
+        let counter = ConnectionCounter::default();
+        let guard = counter.try_add(ip(1), 4).unwrap();
+        assert_eq!(counter.counts.get(&ip(1)).map(|count| *count), Some(1));
+
+        drop(guard);
+        assert!(counter.counts.is_empty());
+    }
+
+    #[test]
+    fn test_connection_refused_not_counted() {
+        let counter = ConnectionCounter::default();
+        assert!(counter.try_add(ip(1), 0).is_none());
+        assert!(counter.counts.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connection_wait_empty() {
+        let counter = Arc::new(ConnectionCounter::default());
+        assert!(counter.try_add(ip(1), 0).is_none());
+
+        // Nothing is open, including the refused connection
+        tokio::time::timeout(Duration::from_secs(1), counter.wait_empty())
+            .await
+            .unwrap();
+
+        let guard = counter.try_add(ip(1), 1).unwrap();
+        let waiter = tokio::spawn({
+            let counter = counter.clone();
+            async move { counter.wait_empty().await }
+        });
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!waiter.is_finished());
+
+        drop(guard);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accept_limiter() {
+        let limiter = KeyedLimiter::new(2, 1.0);
+        assert!(limiter.try_take(ip(1)));
+        assert!(limiter.try_take(ip(1)));
+        assert!(!limiter.try_take(ip(1)));
+        assert!(limiter.is_limited(&ip(1)));
+
+        // Other keys have their own bucket
+        assert!(limiter.try_take(ip(2)));
+        assert!(!limiter.is_limited(&ip(3)));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(!limiter.is_limited(&ip(1)));
+        assert!(limiter.try_take(ip(1)));
+        assert!(!limiter.try_take(ip(1)));
+
+        // Full buckets are removed once they're no longer needed
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(limiter.try_take(ip(3)));
+        limiter.prune();
+        assert_eq!(limiter.buckets.len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_accept_limiter_prune_interval() {
+        let limiter = KeyedLimiter::new(2, 1.0);
+        assert!(limiter.try_take(ip(1)));
+        tokio::time::advance(Duration::from_secs(1)).await;
+
+        // The refilled bucket is only removed once every PRUNE_INTERVAL calls
+        for _ in 2..PRUNE_INTERVAL {
+            limiter.try_take(ip(2));
+        }
+        assert_eq!(limiter.buckets.len(), 2);
+
+        limiter.try_take(ip(2));
+        assert_eq!(limiter.buckets.len(), 1);
+        assert!(limiter.buckets.contains_key(&ip(2)));
+    }
+}
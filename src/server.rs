@@ -1,34 +1,59 @@
-use std::borrow::ToOwned;
 use std::collections::HashSet;
-use std::fmt::Debug;
-use std::net::SocketAddr;
-use std::string::ToString;
+use std::fmt::{Debug, Display};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
 
+use bytes::{Buf, Bytes};
+use chrono::Utc;
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use flume::{Receiver, Sender};
-use futures::future::join_all;
 use futures::stream::{SplitSink, SplitStream};
 use futures::StreamExt;
-use tokio::net::{TcpListener, TcpStream};
+use subtle::ConstantTimeEq;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::sync::RwLock;
 use tokio::time::{self, Duration};
 use tokio_util::codec::Framed;
-use tracing::{debug, error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
-use crate::config::SharedConfig;
-use crate::moons::Moons;
+use crate::config::{Ban, BanTarget, Config, DuplicateNames, NameError, Routing, SharedConfig};
+use crate::moons::{Moon, Moons};
 use crate::packet::{
-    ConnectPacket, ConnectionType, CostumePacket, InitPacket, IntoPacket, MoonPacket, Packet,
-    PacketCodec, PacketData,
+    ChangeStagePacket, ConnectPacket, ConnectionType, CostumePacket, InitPacket, IntoPacket,
+    MoonPacket, Packet, PacketCodec, PacketData, TagPacket, UdpInitPacket, MAX_BODY_LENGTH,
 };
 use crate::peer::Peer;
 use crate::peers::Peers;
 use crate::player::Player;
-use crate::players::Players;
+use crate::players::{NameReservation, Nicknames, Players};
+use crate::profiles::{Profile, Profiles};
+use crate::rate_limit::{ConnectionCounter, KeyedLimiter, PacketLimiter, RateCheck};
+use crate::tag::TagGame;
 use crate::Args;
 
+/// Time connections get to close and save their player when shutting down
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the config file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Size of [`ConnectPacket::nickname`] in bytes
+const NICKNAME_LEN: usize = 0x20;
+
+/// Wrong passwords allowed per IP before connections are refused
+const PASSWORD_ATTEMPTS: u32 = 5;
+
+/// One more password attempt is allowed every minute
+const PASSWORD_REFILL: f64 = 1.0 / 60.0;
+
+/// Marks a password sent after the connect packet, followed by its length as a `u8`
+///
+/// Any other trailing bytes are left alone, newer clients may use them for other fields.
+const PASSWORD_TAG: &[u8] = b"PASS";
+
 pub type Sink = SplitSink<Framed<TcpStream, PacketCodec>, Packet>;
 pub type Stream = SplitStream<Framed<TcpStream, PacketCodec>>;
 
@@ -37,15 +62,26 @@ pub struct Server {
     addr: SocketAddr,
     config: SharedConfig,
 
-    peers: RwLock<Peers>,
-    players: RwLock<Players>,
+    peers: Peers,
+    players: Players,
+    nicknames: Nicknames,
     moons: RwLock<Moons>,
+    profiles: RwLock<Profiles>,
+    tag: RwLock<TagGame>,
+    udp: Option<Arc<UdpSocket>>,
+
+    /// Wrong password attempts per IP
+    password_failures: KeyedLimiter<IpAddr>,
+
+    /// New connections per IP, `None` if the accept rate isn't limited
+    accepts: Option<KeyedLimiter<IpAddr>>,
+    connections: ConnectionCounter,
 
-    process_tx: Sender<(Uuid, Packet)>,
-    process_rx: Receiver<(Uuid, Packet)>,
+    /// Cancelled to stop accepting connections and shut down
+    stop: CancellationToken,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum ReplyType {
     /// Invalid, disconnect peer
     Invalid,
@@ -55,11 +91,40 @@ pub enum ReplyType {
 
     /// Broadcast the reply to everyone except the sender
     Broadcast(Packet),
+
+    /// Broadcast the reply to everyone in the same stage as the sender
+    BroadcastStage(Packet),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PasswordCheck {
+    Disabled,
+    Correct,
+    Wrong,
+
+    /// Too many wrong passwords from the same IP
+    Limited,
+}
+
+/// Where a moderation action came from, shown in the logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Actor {
+    Console,
+    Api,
+}
+
+impl Display for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Actor::Console => write!(f, "Console"),
+            Actor::Api => write!(f, "Admin API"),
+        }
+    }
 }
 
 impl Server {
     pub async fn new(args: &Args, config: SharedConfig) -> Result<Arc<Self>> {
-        let addr = {
+        let (addr, udp_port, limits) = {
             let config = config.read().await;
 
             let port = args.port.or_else(|| config.server.port()).unwrap_or(1027);
@@ -68,21 +133,39 @@ impl Server {
                 .or_else(|| config.server.host())
                 .unwrap_or_else(|| "0.0.0.0".parse().unwrap());
 
-            SocketAddr::from((host, port))
+            (
+                SocketAddr::from((host, port)),
+                config.server.udp_port(),
+                config.server.connections(),
+            )
         };
 
-        let moons = Moons::load(config.clone()).await?;
-        let (p_tx, p_rx) = flume::unbounded();
+        let udp = match udp_port {
+            Some(port) => {
+                let socket = UdpSocket::bind((addr.ip(), port)).await?;
+                Some(Arc::new(socket))
+            }
 
+            None => None,
+        };
+
+        let moons = Moons::load(config.clone()).await?;
+        let profiles = Profiles::load(config.clone()).await?;
         let server = Self {
             addr,
             config,
-            peers: RwLock::default(),
-            players: RwLock::default(),
+            peers: Peers::default(),
+            players: Players::default(),
+            nicknames: Nicknames::default(),
             moons: RwLock::new(moons),
-
-            process_tx: p_tx,
-            process_rx: p_rx,
+            profiles: RwLock::new(profiles),
+            tag: RwLock::default(),
+            udp,
+            password_failures: KeyedLimiter::new(PASSWORD_ATTEMPTS, PASSWORD_REFILL),
+            accepts: (limits.accept_burst > 0)
+                .then(|| KeyedLimiter::new(limits.accept_burst, limits.accept_rate)),
+            connections: ConnectionCounter::default(),
+            stop: CancellationToken::new(),
         };
 
         Ok(Arc::new(server))
@@ -94,51 +177,93 @@ impl Server {
 
         loop {
             let server = self.clone();
-            let (stream, addr) = listener.accept().await?;
+            let (stream, addr) = tokio::select! {
+                accepted = listener.accept() => accepted?,
+                _ = self.stop.cancelled() => return Ok(()),
+            };
+
+            let (limits, frame_mode, connections, limiter) = {
+                let config = self.config.read().await;
+                (
+                    config.server.send_queue(),
+                    config.server.frame_mode(),
+                    config.server.connections(),
+                    PacketLimiter::new(config.server.flood()),
+                )
+            };
+
+            // Refuse floods before spawning anything for them
+            let accepted = match &self.accepts {
+                Some(accepts) => accepts.try_take(addr.ip()),
+                None => true,
+            };
+
+            if !accepted {
+                debug!(?addr, "refused, connecting too often");
+                continue;
+            }
+
+            let guard = match self
+                .connections
+                .try_add(addr.ip(), connections.max_per_ip())
+            {
+                Some(guard) => guard,
+                None => {
+                    debug!(?addr, "refused, too many connections from this IP");
+                    continue;
+                }
+            };
 
             stream.set_nodelay(true)?;
             debug!(?addr, "accepted");
 
             tokio::spawn(async move {
-                let (sink, stream) = Framed::new(stream, PacketCodec).split();
-                let peer = Peer::new(sink, addr);
+                let codec = PacketCodec::new(frame_mode);
+                let (sink, stream) = Framed::new(stream, codec).split();
+                let peer = Peer::new(sink, addr, limits, limiter);
 
-                if let Err(error) = server.handle_connection(stream, peer).await {
+                let timeout = connections.handshake_timeout();
+                if let Err(error) = server.handle_connection(stream, peer, timeout).await {
                     error!(%addr, %error, "connection closed with error");
                 }
 
+                drop(guard);
                 debug!(?addr, "closed");
             });
         }
     }
 
-    async fn handle_connection(self: Arc<Self>, mut stream: Stream, mut peer: Peer) -> Result<()> {
-        let (max_players, banned_ids) = {
+    async fn handle_connection(
+        self: Arc<Self>,
+        mut stream: Stream,
+        mut peer: Peer,
+        handshake_timeout: Duration,
+    ) -> Result<()> {
+        let (max_players, grace) = {
             let config = self.config.read().await;
-            let max_players = config.server.max_players();
-
-            let mut banned_ids = if config.bans.enabled {
-                config.bans.banned_ids.clone()
-            } else {
-                HashSet::new()
-            };
-
-            // Always ban the nil UUID
-            banned_ids.insert(Uuid::nil());
-
-            (max_players, banned_ids)
+            (config.server.max_players(), config.server.reconnect_grace())
         };
 
         let init = InitPacket { max_players };
-        peer.send_nil_uuid(init).await;
+        peer.send_nil_uuid(init);
 
-        let connect_packet = match stream.next().await {
-            Some(packet) => packet?,
-            None => return Ok(()),
+        // Connections that are still joining aren't peers yet, so shutting down has to cancel them
+        let first_packet = tokio::select! {
+            packet = time::timeout(handshake_timeout, stream.next()) => packet,
+            _ = self.stop.cancelled() => return Ok(()),
+        };
+
+        let mut connect_packet = match first_packet {
+            Ok(Some(packet)) => packet?,
+            Ok(None) => return Ok(()),
+            Err(_) => {
+                debug!(addr = ?peer.addr(), "didn't send a connect packet in time");
+                return Ok(());
+            }
         };
 
         let id = connect_packet.id;
-        let connect_data = match connect_packet.data {
+        let mut connect_data = match connect_packet.data {
             PacketData::Connect(data) => data,
             _ => {
                 // First packet must be connect packet
@@ -146,102 +271,160 @@ impl Server {
             }
         };
 
-        // Banned players check
-        if banned_ids.contains(&id) {
+        // Strip the password first, so it isn't part of the nickname
+        let password = self
+            .check_password(&mut connect_data, &mut connect_packet.trailing, peer.addr())
+            .await?;
+
+        // Banned players check, the nil UUID is always banned
+        if id.is_nil() {
+            return Ok(());
+        }
+
+        let name = connect_data.nickname.try_to_string()?;
+        // Held until the connection closes, so nobody else can join with the same name
+        let (name, _reservation) = match self.check_name(&id, &name).await {
+            Ok(checked) => checked,
+            Err(error) => {
+                info!("{name}/{id} tried to join but was rejected: {error}");
+                return Ok(());
+            }
+        };
+
+        // Other players receive the cleaned up nickname
+        connect_data.nickname = name.parse()?;
+        connect_packet.data = PacketData::Connect(connect_data);
+
+        let name = name.as_str();
+        if let Some(ban) = self.find_ban(&id, peer.addr(), name).await {
+            info!("{name}/{id} tried to join but is banned ({ban})");
             return Ok(());
         }
 
-        // Max players check
-        {
-            let peers = self.peers.read().await;
+        let is_whitelisted = {
+            let config = self.config.read().await;
+            config.whitelist.is_allowed(&id)
+        };
 
-            let player_count = peers.count();
-            let max_players = max_players as usize;
+        if !is_whitelisted {
+            info!("{name}/{id} tried to join but isn't whitelisted");
+            return Ok(());
+        }
 
-            if player_count >= max_players {
+        match password {
+            PasswordCheck::Disabled | PasswordCheck::Correct => (),
+            PasswordCheck::Wrong => {
+                info!("{name}/{id} tried to join with the wrong password");
                 return Ok(());
             }
-        }
 
-        // Send state of existing players
-        {
-            let mut peers = self.peers.write().await;
-            let players = self.players.read().await;
+            PasswordCheck::Limited => {
+                info!("{name}/{id} tried to join after too many wrong passwords");
+                return Ok(());
+            }
+        }
 
-            for uuid in peers.keys() {
-                if uuid == id {
-                    continue;
-                }
+        // Max players check, a duplicate connection replaces the existing one
+        if !self.peers.contains(&id) && self.peers.count() >= max_players as usize {
+            return Ok(());
+        }
 
-                let player = match players.get(&uuid) {
-                    Ok(player) => player,
-                    Err(_) => continue,
-                };
+        // Send state of existing players
+        for player in self.players.all_players() {
+            if player.id == id {
+                continue;
+            }
 
-                let packet = ConnectPacket {
-                    connection_type: ConnectionType::Init,
-                    max_players,
-                    nickname: player.name.clone().parse()?,
-                };
+            let packet = ConnectPacket {
+                connection_type: ConnectionType::Init,
+                max_players,
+                nickname: player.name.clone().parse()?,
+            };
 
-                let packet = packet.into_packet(player.id);
-                peer.send(packet).await;
+            let packet = packet.into_packet(player.id);
+            peer.send(packet);
 
-                if let Some(costume) = &player.costume {
-                    let costume_packet: CostumePacket = costume.clone().try_into()?;
-                    let costume_packet = costume_packet.into_packet(player.id);
+            if let Some(costume) = &player.costume {
+                let costume_packet: CostumePacket = costume.clone().try_into()?;
+                let costume_packet = costume_packet.into_packet(player.id);
 
-                    peer.send(costume_packet).await;
-                }
+                peer.send(costume_packet);
             }
+        }
 
-            // Insert peer into server state
-            peers.insert(id, peer);
+        // Let the client know where to hole punch, movement packets switch to UDP once it does
+        if let Some(udp) = &self.udp {
+            let port = udp.local_addr()?.port();
+            peer.send_nil_uuid(UdpInitPacket { port });
         }
 
+        // Insert peer into server state
+        let addr = peer.addr();
+        let closed = peer.closed();
+        peer.id = id;
+        self.peers.insert(id, peer);
+
         // Capture errors instead of returning
         let server = self.clone();
         let run = || async move {
             // Insert player into server state
-            {
-                let mut players = server.players.write().await;
-                match players.get(&connect_packet.id).ok() {
-                    Some(player) => {
-                        // Reconnect
-                        info!("{player} reconnected");
-                    }
+            let name = connect_data.nickname.try_to_string()?;
+            let restored = connect_data.connection_type == ConnectionType::Reconnect
+                && server.players.restore(&id, grace);
 
-                    None => {
-                        // First connect
-                        let name = connect_data.nickname.try_to_string()?;
-                        let player = Player::new(connect_packet.id, name);
+            if restored {
+                let mut player = server.players.get_mut(&id)?;
+                player.name = name;
 
-                        info!("{player} connected");
-                        let _ = players.insert(id, player);
-                    }
+                info!("{} reconnected", *player);
+            } else {
+                let player = Player::new(id, name);
+
+                info!("{player} connected");
+
+                // The replaced connection's cleanup skips its player, so its session is saved here
+                if let Some(old) = server.players.insert(id, player) {
+                    server.save_replaced_profile(&old).await?;
                 }
             }
 
-            // Broadcast connect and costume packets to other clients in the background
-            {
-                let mut peers = server.peers.write().await;
-                peers.broadcast(connect_packet).await;
+            server.save_profile(&id, false).await?;
 
-                let players = server.players.read().await;
-                let player = players.get(&connect_packet.id)?;
+            // Broadcast connect and costume packets to other clients in the background
+            server.peers.broadcast(connect_packet);
 
-                if let Some(costume) = &player.costume {
-                    let costume_packet: CostumePacket = costume.clone().try_into()?;
-                    let costume_packet = costume_packet.into_packet(connect_packet.id);
+            let costume = server.players.get(&id)?.costume.clone();
+            if let Some(costume) = costume {
+                let costume_packet: CostumePacket = costume.try_into()?;
+                let costume_packet = costume_packet.into_packet(id);
 
-                    peers.broadcast(costume_packet).await;
-                }
+                server.peers.broadcast(costume_packet);
             }
 
-            // TODO: Send queue
-            while let Some(packet) = stream.next().await {
-                let packet = packet?;
-                server.process_tx.send((id, packet))?;
+            // Packets are processed as they are received, so peers don't wait on each other
+            loop {
+                // Also stop if the server shut down before this peer was added
+                let packet = tokio::select! {
+                    packet = stream.next() => packet,
+                    _ = closed.cancelled() => break,
+                    _ = server.stop.cancelled() => break,
+                };
+
+                let packet = match packet {
+                    Some(packet) => packet?,
+                    None => break,
+                };
+
+                let data = packet.data.clone();
+                match server.handle_packet(id, packet).await {
+                    Ok(true) => (),
+                    Ok(false) => break,
+
+                    Err(error) => {
+                        error!(%id, packet = ?data, "error occurred while processing packet");
+                        return Err(error);
+                    }
+                }
             }
 
             Ok(())
@@ -249,55 +432,176 @@ impl Server {
 
         // Bubble up errors and always run disconnect logic
         let result = run().await;
-        let disconnect_packet = Packet {
-            id,
-            data: PacketData::Disconnect,
+
+        // Nothing else to clean up if a newer connection replaced this one
+        if self.peers.remove(&id, addr).is_none() {
+            return result;
+        }
+
+        let grace = {
+            let config = self.config.read().await;
+            config.server.reconnect_grace()
         };
 
-        // Disconnect socket and broadcast to other clients
-        {
-            let mut peers = self.peers.write().await;
-            peers.remove(&id, &self.players).await;
-            peers.broadcast(disconnect_packet).await;
+        if let Err(error) = self.save_profile(&id, true).await {
+            error!(%id, %error, "failed to save profile");
         }
 
+        // Disconnect socket and broadcast to other clients
+        self.players.disconnect(&id, grace);
+
+        let disconnect_packet = Packet::new(id, PacketData::Disconnect);
+        self.peers.broadcast(disconnect_packet);
+
         result
     }
 
-    // region: Packet Sending
-    pub async fn broadcast(self: &Arc<Self>, packet: Packet) -> Result<()> {
-        let mut peers = self.peers.write().await;
-        peers.broadcast(packet).await;
+    // region: Shutdown
+    /// Stop accepting connections, [`Server::shutdown`] should be called after
+    #[inline]
+    pub fn stop(&self) {
+        self.stop.cancel();
+    }
+
+    /// Wait until [`Server::stop`] is called
+    #[inline]
+    pub async fn stopped(&self) {
+        self.stop.cancelled().await;
+    }
+
+    /// Disconnect every player, then save moons and profiles
+    ///
+    /// The config isn't saved, every change is written when it's made and saving
+    /// here could overwrite edits to the file that failed to load.
+    pub async fn shutdown(&self) -> Result<()> {
+        info!("Shutting down...");
+        self.stop();
+        self.peers.disconnect_all();
+
+        // Connections save their player's profile when they close
+        if time::timeout(SHUTDOWN_TIMEOUT, self.connections.wait_empty())
+            .await
+            .is_err()
+        {
+            warn!("Timed out waiting for connections to close");
+        }
 
+        // Write locks wait for saves that are already in progress
+        {
+            let moons = self.moons.write().await;
+            moons.save().await?;
+        }
+
+        {
+            let profiles = self.profiles.write().await;
+            profiles.save().await?;
+        }
+
+        info!("Saved moons and profiles");
         Ok(())
     }
+    // endregion
 
-    pub async fn broadcast_some(
-        self: &Arc<Self>,
+    // region: UDP
+    pub async fn listen_udp(self: Arc<Self>) -> Result<()> {
+        let socket = match &self.udp {
+            Some(socket) => socket.clone(),
+            None => return Ok(()),
+        };
+
+        info!("UDP listening on {}", socket.local_addr()?);
+        let mut buf = [0; MAX_BODY_LENGTH + Packet::buf_size()];
+
+        loop {
+            let received = tokio::select! {
+                received = socket.recv_from(&mut buf) => received,
+                _ = self.stop.cancelled() => return Ok(()),
+            };
+
+            let (len, addr) = match received {
+                Ok(received) => received,
+                Err(error) => {
+                    debug!(%error, "udp receive failed");
+                    continue;
+                }
+            };
+
+            let packet = match Packet::from_bytes(Bytes::copy_from_slice(&buf[..len])) {
+                Ok(packet) => packet,
+                Err(error) => {
+                    debug!(%addr, %error, "invalid datagram");
+                    continue;
+                }
+            };
+
+            if let Err(error) = self.handle_datagram(&socket, packet, addr).await {
+                debug!(%addr, %error, "error occurred while processing datagram");
+            }
+        }
+    }
+
+    async fn handle_datagram(
+        &self,
+        socket: &Arc<UdpSocket>,
         packet: Packet,
-        players: HashSet<Uuid>,
+        addr: SocketAddr,
     ) -> Result<()> {
-        let mut peers = self.peers.write().await;
-        peers.broadcast_some(packet, players).await;
+        let id = packet.id;
+        match packet.data {
+            PacketData::HolePunch => {
+                let bound = self.peers.bind_udp(&id, socket.clone(), addr);
+                debug!(%id, %addr, bound, "udp hole punch");
+            }
+
+            // Only accept movement from the address bound to the peer
+            PacketData::Player(_) | PacketData::Cap(_)
+                if self.peers.udp_addr(&id) == Some(addr) =>
+            {
+                self.handle_packet(id, packet).await?;
+            }
+
+            _ => (),
+        }
 
         Ok(())
     }
     // endregion
 
+    // region: Packet Sending
+    #[inline]
+    pub fn broadcast(&self, packet: Packet) {
+        self.peers.broadcast(packet);
+    }
+
+    #[inline]
+    pub fn broadcast_some(&self, packet: Packet, players: HashSet<Uuid>) {
+        self.peers.broadcast_some(packet, &players);
+    }
+    // endregion
+
     // region: Player Info
-    pub async fn list_players(self: &Arc<Self>) -> HashSet<String> {
-        let all_players = self.players.read().await;
-        all_players.all_players().map(ToString::to_string).collect()
+    pub fn list_players(&self) -> HashSet<String> {
+        self.players
+            .all_players()
+            .map(|player| player.to_string())
+            .collect()
+    }
+
+    /// Map every connected player, without holding references to them
+    pub fn map_players<T>(&self, mut f: impl FnMut(&Player) -> T) -> Vec<T> {
+        self.players
+            .all_players()
+            .map(|player| f(&player))
+            .collect()
     }
 
-    pub async fn resolve_players(self: &Arc<Self>, mut players: Vec<String>) -> HashSet<Uuid> {
+    pub fn resolve_players(&self, mut players: Vec<String>) -> HashSet<Uuid> {
         let is_all = players.contains(&"*".to_owned());
         for player in players.iter_mut() {
             *player = player.to_lowercase();
         }
 
-        let all_players = self.players.read().await;
-        all_players
+        self.players
             .all_players()
             .filter(|player| {
                 if is_all {
@@ -315,35 +619,35 @@ impl Server {
     // endregion
 
     // region: Packet Processing
-    pub async fn process_packets(self: Arc<Self>) {
-        while let Ok((id, packet)) = self.process_rx.recv_async().await {
-            let server = self.clone();
-            let disconnect = || async move {
-                let mut peers = server.peers.write().await;
-                let _ = peers.remove(&id, &server.players).await;
-            };
-
-            let reply = match self.process_packet(id, packet).await {
-                Ok(reply) => reply,
+    /// Process a packet and send any replies
+    ///
+    /// Returns `false` if the peer should be disconnected.
+    async fn handle_packet(&self, id: Uuid, packet: Packet) -> Result<bool> {
+        match self.peers.check_rate(&id, &packet.data) {
+            RateCheck::Allow => (),
+            RateCheck::Drop => {
+                trace!(%id, packet = packet.data.name(), "dropped packet over rate limit");
+                return Ok(true);
+            }
 
-                Err(error) => {
-                    error!(%id, packet = ?packet.data, "error occurred while processing packet");
-                    eprintln!("{:?}", error);
+            RateCheck::Flood => {
+                self.kick_flooder(&id, packet.data.name()).await?;
+                return Ok(false);
+            }
+        }
 
-                    disconnect().await;
-                    continue;
-                }
-            };
+        match self.process_packet(id, packet).await? {
+            ReplyType::None => (),
+            ReplyType::Invalid => return Ok(false),
+            ReplyType::Broadcast(packet) => self.peers.broadcast(packet),
 
-            match reply {
-                ReplyType::None => (),
-                ReplyType::Invalid => disconnect().await,
-                ReplyType::Broadcast(packet) => {
-                    let mut peers = self.peers.write().await;
-                    peers.broadcast(packet).await;
-                }
-            }
+            ReplyType::BroadcastStage(packet) => match self.stage_recipients(&packet.id) {
+                Some(recipients) => self.peers.broadcast_some(packet, &recipients),
+                None => self.peers.broadcast(packet),
+            },
         }
+
+        Ok(true)
     }
 
     async fn process_packet(&self, id: Uuid, packet: Packet) -> Result<ReplyType> {
@@ -351,113 +655,603 @@ impl Server {
             PacketData::Disconnect | PacketData::Init(_) => ReplyType::Invalid,
 
             PacketData::Game(data) => {
-                let mut players = self.players.write().await;
-                let player = players.get_mut(&id)?;
+                {
+                    let mut player = self.players.get_mut(&id)?;
 
-                let last_game = player.last_game.unwrap_or_default();
-                if last_game.stage != data.stage || last_game.scenario != data.scenario {
-                    info!("{player} -> {}/{}", data.stage, data.scenario);
-                }
+                    let last_game = player.last_game.unwrap_or_default();
+                    if last_game.stage != data.stage || last_game.scenario != data.scenario {
+                        info!("{} -> {}/{}", *player, data.stage, data.scenario);
+                    }
 
-                player.last_game = Some(*data);
+                    player.set_game(*data);
+                }
 
                 // Send the position of all players when a player join a stage
                 // If we don't do so, people are gonna be invisible or to their previous position until they move
-                let mut peers = self.peers.write().await;
-                let peer = peers.get_mut(&id);
-
-                if let Ok(peer) = peer {
-                    let players = players.all_players();
-                    let positions = players.into_iter().map(|player| {
-                        let stage = player.stage().map(ToOwned::to_owned);
-                        let pos = player.last_pos.as_ref().copied();
-
-                        (stage, player.id, pos)
-                    });
-
-                    let self_stage = data.stage.try_as_str()?;
-                    for (stage, id, last_position) in positions {
-                        if let (Some(player_stage), Some(last_packet)) = (stage, last_position) {
-                            if player_stage == self_stage {
-                                let packet = last_packet.into_packet(id);
-                                peer.send(packet).await;
-                            }
-                        }
-                    }
+                let self_stage = data.stage.try_as_str()?;
+                let positions = self
+                    .players
+                    .all_players()
+                    .filter(|player| player.id != id && player.stage() == Some(self_stage))
+                    .filter_map(|player| player.last_pos.map(|pos| pos.into_packet(player.id)))
+                    .collect::<Vec<_>>();
+
+                for packet in positions {
+                    self.peers.send(&id, packet);
                 }
 
                 ReplyType::Broadcast(packet)
             }
 
             PacketData::Costume(data) => {
+                self.players.get_mut(&id)?.set_costume(*data)?;
+
+                let costume = self.sanitize_costume(&id, data).await?;
+                let mut outgoing = costume.into_packet(packet.id);
+                outgoing.trailing = packet.trailing.clone();
+
+                self.sync_moons_inner().await?;
+                ReplyType::Broadcast(outgoing)
+            }
+
+            PacketData::Moon(data) => {
+                // Insert moons
+                let moon = {
+                    let mut player = self.players.get_mut(&id)?;
+                    if !player.moons.contains(&data.id) {
+                        info!("{} collected moon {}", *player, data.id);
+                        player.moons.insert(data.id);
+                    }
+
+                    Moon::collected(data.id, data.is_grand, &player)
+                };
+
                 {
-                    let mut players = self.players.write().await;
-                    let player = players.get_mut(&id)?;
+                    let mut moons = self.moons.write().await;
+                    moons.insert(moon).await?;
+                }
 
-                    player.set_costume(*data)?;
+                self.sync_moons_inner().await?;
+                ReplyType::Broadcast(packet)
+            }
+
+            PacketData::Tag(data) => {
+                if data.update_bits & TagPacket::UPDATE_STATE != 0 {
+                    let mut tag = self.tag.write().await;
+                    if tag.is_running() {
+                        tag.set_seeker(id, data.is_it);
+                    }
                 }
 
-                let fallback = "Mario".parse().unwrap();
-                let cap = data.cap.try_to_string()?;
-                let body = data.body.try_to_string()?;
+                self.check_tag_round().await;
+                ReplyType::Broadcast(packet)
+            }
 
-                let (is_allowed, is_cap_banned, is_body_banned) = {
-                    let config = self.config.read().await;
+            PacketData::Player(data) => {
+                self.players.get_mut(&id)?.last_pos = Some(*data);
+                self.movement_reply(packet).await
+            }
 
-                    let is_allowed = config.costumes.is_allowed(&id);
-                    let is_cap_banned = config.costumes.is_banned(&cap);
-                    let is_body_banned = config.costumes.is_banned(&body);
+            PacketData::Cap(_) | PacketData::Capture(_) => self.movement_reply(packet).await,
 
-                    (is_allowed, is_cap_banned, is_body_banned)
-                };
+            // Broadcast as-is
+            PacketData::ChangeStage(_) => ReplyType::Broadcast(packet),
 
-                let body = match (is_body_banned, is_allowed) {
-                    (true, false) => fallback,
-                    _ => body.parse()?,
+            PacketData::Raw { packet_id, .. } => {
+                let relayed = {
+                    let config = self.config.read().await;
+                    config.server.relays_raw(*packet_id)
                 };
 
-                let cap = match (is_cap_banned, is_allowed) {
-                    (true, false) => fallback,
-                    _ => cap.parse()?,
-                };
+                if relayed {
+                    ReplyType::Broadcast(packet)
+                } else {
+                    debug!(%id, packet_id, "dropping unknown packet");
+                    ReplyType::None
+                }
+            }
+
+            // Hole punching is only handled over UDP
+            PacketData::Connect(_) | PacketData::UdpInit(_) | PacketData::HolePunch => {
+                ReplyType::None
+            }
+        };
 
-                let outgoing = CostumePacket { body, cap };
-                let outgoing = outgoing.into_packet(packet.id);
+        Ok(reply)
+    }
 
-                self.sync_moons_inner().await?;
-                ReplyType::Broadcast(outgoing)
+    async fn movement_reply(&self, packet: Packet) -> ReplyType {
+        let routing = {
+            let config = self.config.read().await;
+            config.server.routing()
+        };
+
+        match routing {
+            Routing::Global => ReplyType::Broadcast(packet),
+            Routing::Stage => ReplyType::BroadcastStage(packet),
+        }
+    }
+
+    /// Players in the same stage as `id`, or `None` if their stage is unknown
+    ///
+    /// Players that haven't sent their stage yet are always included.
+    fn stage_recipients(&self, id: &Uuid) -> Option<HashSet<Uuid>> {
+        let stage = self.players.get(id).ok()?.stage()?.to_owned();
+        let recipients = self
+            .players
+            .all_players()
+            .filter(|player| match player.stage() {
+                Some(player_stage) => player_stage == stage,
+                None => true,
+            })
+            .map(|player| player.id)
+            .collect();
+
+        Some(recipients)
+    }
+    // endregion
+
+    // region: Moderation
+    /// Replace banned parts of a costume, unless the player is allowed to use them
+    async fn sanitize_costume(&self, id: &Uuid, data: &CostumePacket) -> Result<CostumePacket> {
+        let fallback = "Mario".parse().unwrap();
+        let cap = data.cap.try_to_string()?;
+        let body = data.body.try_to_string()?;
+
+        let (is_allowed, is_cap_banned, is_body_banned) = {
+            let config = self.config.read().await;
+
+            let is_allowed = config.costumes.is_allowed(id);
+            let is_cap_banned = config.costumes.is_banned(&cap);
+            let is_body_banned = config.costumes.is_banned(&body);
+
+            (is_allowed, is_cap_banned, is_body_banned)
+        };
+
+        let body = match (is_body_banned, is_allowed) {
+            (true, false) => fallback,
+            _ => body.parse()?,
+        };
+
+        let cap = match (is_cap_banned, is_allowed) {
+            (true, false) => fallback,
+            _ => cap.parse()?,
+        };
+
+        Ok(CostumePacket { body, cap })
+    }
+
+    /// Send every player's costume again, after the costume bans changed
+    async fn broadcast_costumes(&self) -> Result<()> {
+        let costumes = self
+            .players
+            .all_players()
+            .filter_map(|player| Some((player.id, player.costume.clone()?)))
+            .collect::<Vec<_>>();
+
+        for (id, costume) in costumes {
+            let costume: CostumePacket = costume.try_into()?;
+            let costume = self.sanitize_costume(&id, &costume).await?;
+
+            self.peers.broadcast(costume.into_packet(id));
+        }
+
+        Ok(())
+    }
+
+    /// Check the password sent by a connecting client, removing it from the packet
+    async fn check_password(
+        &self,
+        data: &mut ConnectPacket,
+        trailing: &mut Bytes,
+        addr: SocketAddr,
+    ) -> Result<PasswordCheck> {
+        let expected = {
+            let config = self.config.read().await;
+            match config.server.password() {
+                Some(password) => password.to_owned(),
+                None => return Ok(PasswordCheck::Disabled),
             }
+        };
 
-            PacketData::Moon(data) => {
-                // Insert moons
-                {
-                    let mut players = self.players.write().await;
-                    let player = players.get_mut(&id)?;
+        let password = take_password(data, trailing)?;
 
-                    let mut moons = self.moons.write().await;
-                    moons.insert(data.id).await?;
+        let ip = addr.ip();
+        if self.password_failures.is_limited(&ip) {
+            return Ok(PasswordCheck::Limited);
+        }
 
-                    if player.moons.get(&data.id).is_none() {
-                        info!("{player} collected moon {}", data.id);
-                        player.moons.insert(data.id);
+        let correct = password
+            .is_some_and(|password| bool::from(password.as_bytes().ct_eq(expected.as_bytes())));
+
+        if correct {
+            Ok(PasswordCheck::Correct)
+        } else {
+            self.password_failures.try_take(ip);
+            Ok(PasswordCheck::Wrong)
+        }
+    }
+
+    /// Disconnect a player that kept going over the packet rate limits, banning them if configured
+    async fn kick_flooder(&self, id: &Uuid, packet: &str) -> Result<()> {
+        let player = self.player_name(id);
+        warn!(%id, packet, "{player} is flooding packets, disconnecting");
+
+        self.peers.disconnect(id);
+
+        let mut config = self.config.write().await;
+        if let Some(duration) = config.server.flood().ban_duration() {
+            let ban = Ban {
+                target: BanTarget::Id(*id),
+                reason: Some(format!("flooding {packet} packets")),
+                expires: Some(Utc::now() + chrono::Duration::from_std(duration)?),
+            };
+
+            info!("Banned {ban}");
+            config.bans.add(ban);
+            config.save().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply the nickname rules to a joining player, returns the nickname they should use
+    /// and its reservation
+    async fn check_name(
+        &self,
+        id: &Uuid,
+        name: &str,
+    ) -> Result<(String, NameReservation), NameError> {
+        let config = self.config.read().await;
+        let name = config.names.sanitize(name)?;
+
+        if let Some(reservation) = self.nicknames.reserve(*id, &name) {
+            return Ok((name, reservation));
+        }
+
+        match config.names.duplicates {
+            DuplicateNames::Reject => Err(NameError::Duplicate),
+            DuplicateNames::Suffix => {
+                let mut number = 2;
+                loop {
+                    let suffixed = suffixed_name(&name, number);
+                    if let Some(reservation) = self.nicknames.reserve(*id, &suffixed) {
+                        return Ok((suffixed, reservation));
                     }
+
+                    number += 1;
                 }
+            }
+        }
+    }
 
-                self.sync_moons_inner().await?;
-                ReplyType::Broadcast(packet)
+    async fn find_ban(&self, id: &Uuid, addr: SocketAddr, name: &str) -> Option<Ban> {
+        let config = self.config.read().await;
+        if !config.bans.enabled {
+            return None;
+        }
+
+        config.bans.find(id, addr.ip(), name)
+    }
+
+    /// Disconnect connected players, returns the players that were kicked
+    pub fn kick(&self, players: &HashSet<Uuid>, reason: Option<&str>, by: Actor) -> Vec<Uuid> {
+        let mut kicked = vec![];
+        for id in players {
+            let player = self.player_name(id);
+            if self.peers.disconnect(id) {
+                info!("{by} kicked {player} ({})", reason.unwrap_or("no reason"));
+                kicked.push(*id);
             }
+        }
 
-            // Broadcast as-is
-            PacketData::Player(_)
-            | PacketData::Cap(_)
-            | PacketData::Tag(_)
-            | PacketData::Capture(_)
-            | PacketData::ChangeStage(_) => ReplyType::Broadcast(packet),
+        kicked
+    }
 
-            _ => ReplyType::None,
+    /// Add bans for `targets` and kick connected players matching any ban,
+    /// returns the players that were kicked
+    pub async fn ban(
+        &self,
+        targets: Vec<BanTarget>,
+        reason: Option<String>,
+        duration: Option<Duration>,
+        by: Actor,
+    ) -> Result<Vec<Uuid>> {
+        let expires = match duration {
+            Some(duration) => Some(Utc::now() + chrono::Duration::from_std(duration)?),
+            None => None,
         };
 
-        Ok(reply)
+        let enabled = {
+            let mut config = self.config.write().await;
+            for target in targets {
+                let ban = Ban {
+                    target,
+                    reason: reason.clone(),
+                    expires,
+                };
+
+                info!("{by} banned {ban}");
+                config.bans.add(ban);
+            }
+
+            config.save().await?;
+            config.bans.enabled
+        };
+
+        if !enabled {
+            warn!("Bans are disabled in the config, nobody was kicked");
+            return Ok(vec![]);
+        }
+
+        Ok(self.kick_banned().await)
+    }
+
+    /// Remove bans whose target is written as `target`, returns the number removed
+    pub async fn unban(&self, target: &str, by: Actor) -> Result<usize> {
+        let mut config = self.config.write().await;
+        let removed = config.bans.remove(target);
+        config.save().await?;
+
+        if removed > 0 {
+            info!("{by} removed {removed} ban(s) for {target}");
+        }
+
+        Ok(removed)
+    }
+
+    /// Send players back to the title screen with an invalid stage change, then disconnect them
+    pub fn crash(&self, players: &HashSet<Uuid>, reason: Option<&str>, by: Actor) -> Result<usize> {
+        let packet = ChangeStagePacket {
+            stage: "$agogusStage".parse()?,
+            id: "$among$us".parse()?,
+            scenario: 21,
+            sub_scenario: 69,
+        };
+
+        let packet = packet.into_packet(Uuid::nil());
+
+        let mut crashed = 0;
+        for id in players {
+            let player = self.player_name(id);
+
+            // Already queued packets are still sent when disconnecting
+            self.peers.send(id, packet.clone());
+            if self.peers.disconnect(id) {
+                info!("{by} crashed {player} ({})", reason.unwrap_or("no reason"));
+                crashed += 1;
+            }
+        }
+
+        Ok(crashed)
+    }
+
+    #[inline]
+    fn player_name(&self, id: &Uuid) -> String {
+        match self.players.get(id) {
+            Ok(player) => player.to_string(),
+            Err(_) => id.to_string(),
+        }
+    }
+
+    /// Disconnect every connected player matching a ban, returns the players that were kicked
+    pub async fn kick_banned(&self) -> Vec<Uuid> {
+        let players = self
+            .peers
+            .addrs()
+            .into_iter()
+            .filter_map(|(id, addr)| {
+                let name = self.players.get(&id).ok()?.name.clone();
+                Some((id, addr, name))
+            })
+            .collect::<Vec<_>>();
+
+        let mut kicked = vec![];
+        for (id, addr, name) in players {
+            if let Some(ban) = self.find_ban(&id, addr, &name).await {
+                info!("Kicking {name}/{id} ({ban})");
+
+                self.peers.disconnect(&id);
+                kicked.push(id);
+            }
+        }
+
+        kicked
+    }
+    // endregion
+
+    // region: Profiles
+    /// Update a connected player's profile and write it to disk
+    async fn save_profile(&self, id: &Uuid, disconnected: bool) -> Result<()> {
+        let mut profiles = self.profiles.write().await;
+
+        {
+            let player = self.players.get(id)?;
+            if disconnected {
+                profiles.disconnect(&player);
+            } else {
+                profiles.connect(&player);
+            }
+        }
+
+        profiles.save().await
+    }
+
+    /// Record the end of a session for a player replaced by a newer connection
+    async fn save_replaced_profile(&self, player: &Player) -> Result<()> {
+        let mut profiles = self.profiles.write().await;
+        profiles.disconnect(player);
+
+        profiles.save().await
+    }
+
+    /// All known profiles, most recently seen first
+    pub async fn list_profiles(&self) -> Vec<Profile> {
+        let profiles = self.profiles.read().await;
+
+        let mut list = profiles.all_profiles().cloned().collect::<Vec<_>>();
+        list.sort_by_key(|profile| std::cmp::Reverse(profile.last_seen));
+
+        list
+    }
+
+    /// Profiles matching a UUID or any nickname the player has used
+    pub async fn find_profiles(&self, query: &str) -> Vec<Profile> {
+        let profiles = self.profiles.read().await;
+
+        if let Ok(id) = query.parse::<Uuid>() {
+            return profiles.get(&id).cloned().into_iter().collect();
+        }
+
+        let query = query.to_lowercase();
+        profiles
+            .all_profiles()
+            .filter(|profile| {
+                profile
+                    .names
+                    .iter()
+                    .any(|name| name.to_lowercase() == query)
+            })
+            .cloned()
+            .collect()
+    }
+    // endregion
+
+    // region: Config Reloading
+    /// Reload the config whenever the file is modified
+    pub async fn watch_config(self: Arc<Self>) -> Result<()> {
+        let mut modified = Config::modified().await;
+
+        loop {
+            tokio::select! {
+                _ = time::sleep(CONFIG_POLL_INTERVAL) => (),
+                _ = self.stop.cancelled() => return Ok(()),
+            }
+
+            let current = Config::modified().await;
+            if current == modified {
+                continue;
+            }
+
+            modified = current;
+            if let Err(error) = self.reload_config().await {
+                warn!("Failed to reload config, keeping the current one: {error:#}");
+            }
+        }
+    }
+
+    /// Read the config file and apply the settings that changed
+    pub async fn reload_config(&self) -> Result<()> {
+        let new = Config::read().await?;
+        let old = {
+            let mut config = self.config.write().await;
+            if *config == new {
+                return Ok(());
+            }
+
+            std::mem::replace(&mut *config, new)
+        };
+
+        // Compare before acting, moons and profiles lock the config again when saving
+        let (restart, new_connections, flood, bans, costumes, moons, profiles) = {
+            let new = self.config.read().await;
+
+            let mut restart = vec![];
+            let mut new_connections = vec![];
+            if old.server.host() != new.server.host() || old.server.port() != new.server.port() {
+                restart.push("server address");
+            }
+
+            if old.server.udp_port() != new.server.udp_port() {
+                restart.push("UDP port");
+            }
+
+            let (old_limits, new_limits) = (old.server.connections(), new.server.connections());
+            if old_limits.accept_burst != new_limits.accept_burst
+                || old_limits.accept_rate != new_limits.accept_rate
+            {
+                restart.push("connection accept rate");
+            }
+
+            // Read when a connection is accepted, connected players keep the old settings
+            if old_limits.max_per_ip != new_limits.max_per_ip {
+                new_connections.push("connections per IP limit");
+            }
+
+            if old_limits.handshake_timeout != new_limits.handshake_timeout {
+                new_connections.push("handshake timeout");
+            }
+
+            if old.server.send_queue() != new.server.send_queue() {
+                new_connections.push("send queue limits");
+            }
+
+            if old.server.frame_mode() != new.server.frame_mode() {
+                new_connections.push("frame mode");
+            }
+
+            if old.api != new.api {
+                restart.push("admin API");
+            }
+
+            (
+                restart,
+                new_connections,
+                (old.server.flood() != new.server.flood()).then(|| new.server.flood().clone()),
+                old.bans != new.bans,
+                old.costumes != new.costumes,
+                persist_change(
+                    (old.moons.persist, &old.moons.persist_file),
+                    (new.moons.persist, &new.moons.persist_file),
+                ),
+                persist_change(
+                    (old.profiles.persist, &old.profiles.persist_file),
+                    (new.profiles.persist, &new.profiles.persist_file),
+                ),
+            )
+        };
+
+        info!("Reloaded config");
+        for setting in restart {
+            warn!("The {setting} changed, restart the server to apply it");
+        }
+
+        for setting in new_connections {
+            info!("New connections will use the changed {setting}");
+        }
+
+        if let Some(flood) = flood {
+            self.peers.set_flood_limits(&flood);
+            info!("Applied the new flood limits to connected players");
+        }
+
+        if bans {
+            self.kick_banned().await;
+        }
+
+        if costumes {
+            self.broadcast_costumes().await?;
+        }
+
+        if let (true, old_file) = moons {
+            {
+                let moons = self.moons.read().await;
+                moons.save().await?;
+            }
+
+            remove_moved_file(old_file).await?;
+        }
+
+        if let (true, old_file) = profiles {
+            {
+                let profiles = self.profiles.read().await;
+                profiles.save().await?;
+            }
+
+            remove_moved_file(old_file).await?;
+        }
+
+        Ok(())
     }
     // endregion
 
@@ -472,21 +1266,63 @@ impl Server {
     }
 
     pub async fn reload_moons(self: &Arc<Self>) -> Result<()> {
-        let mut moons = self.moons.write().await;
-        moons.reload().await?;
+        {
+            let mut moons = self.moons.write().await;
+            moons.reload().await?;
+        }
 
         self.sync_moons_inner().await
     }
 
     pub async fn clear_moons(self: &Arc<Self>) -> Result<()> {
-        let mut moons = self.moons.write().await;
-        moons.clear().await?;
+        {
+            let mut moons = self.moons.write().await;
+            moons.clear().await?;
+        }
 
         self.sync_moons_inner().await
     }
 
-    pub async fn give_moon(self: &Arc<Self>, moon: i32) -> Result<()> {
-        todo!()
+    /// Add a moon to the shared moon list and send it to players
+    ///
+    /// The moon is sent to `players` (or everyone if `None`) even if they
+    /// already have it, which allows restoring moons lost from a save file.
+    /// Returns the connected players the moon was sent to.
+    pub async fn give_moon(
+        self: &Arc<Self>,
+        id: i32,
+        is_grand: bool,
+        players: Option<HashSet<Uuid>>,
+    ) -> Result<Vec<Uuid>> {
+        {
+            let mut moons = self.moons.write().await;
+            moons.insert(Moon::new(id, is_grand)).await?;
+        }
+
+        let selected = self
+            .players
+            .all_players_mut()
+            .filter(|player| match &players {
+                Some(players) => players.contains(&player.id),
+                None => true,
+            })
+            .map(|mut player| {
+                player.moons.insert(id);
+                player.id
+            })
+            .collect::<Vec<_>>();
+
+        for player in &selected {
+            let packet = MoonPacket { id, is_grand };
+            self.peers.send(player, packet.into_packet(Uuid::nil()));
+        }
+
+        Ok(selected)
+    }
+
+    pub async fn list_moons(self: &Arc<Self>) -> Vec<Moon> {
+        let moons = self.moons.read().await;
+        moons.all_moons().cloned().collect()
     }
 
     pub async fn sync_moons(self: Arc<Self>) -> Result<()> {
@@ -494,40 +1330,296 @@ impl Server {
     }
 
     async fn sync_moons_inner(&self) -> Result<()> {
-        let mut players = self.players.write().await;
-        let jobs = players
-            .all_players_mut()
-            .map(|player| async move { self.sync_player_moons(player).await });
+        let moons = self.moons.read().await;
+        let config = self.config.read().await;
+
+        let mut outgoing = vec![];
+        for mut player in self.players.all_players_mut() {
+            // Moons that aren't synced yet are retried on the next sync
+            let diff = moons
+                .difference(&player.moons)
+                .into_iter()
+                .filter(|moon| config.moons.sync.should_sync(moon, &player))
+                .map(|moon| MoonPacket {
+                    id: moon.id,
+                    is_grand: moon.is_grand,
+                })
+                .collect::<Vec<_>>();
+
+            for packet in diff {
+                player.moons.insert(packet.id);
+                outgoing.push((player.id, packet));
+            }
+        }
 
-        let results = join_all(jobs).await;
-        let _ = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+        for (id, packet) in outgoing {
+            self.peers.send(&id, packet.into_packet(Uuid::nil()));
+        }
 
         Ok(())
     }
+    // endregion
 
-    async fn sync_player_moons(&self, player: &mut Player) -> Result<()> {
-        let moons = self.moons.read().await;
-        let diff = moons.difference(&player.moons);
+    // region: Hide and Seek
+    pub async fn tag_loop(self: Arc<Self>) -> Result<()> {
+        let duration = Duration::from_secs(1);
+        let mut interval = time::interval(duration);
 
-        if diff.is_empty() {
-            return Ok(());
+        loop {
+            interval.tick().await;
+
+            let (time_up, packet) = {
+                let mut tag = self.tag.write().await;
+                if !tag.is_running() {
+                    continue;
+                }
+
+                let time_up = tag.tick(duration);
+                (time_up, tag.time_packet())
+            };
+
+            self.send_tag_time(packet);
+            if time_up {
+                self.end_tag_round("Time ran out, hiders win!").await;
+            }
         }
+    }
+
+    /// Start a round with the current seekers, returns `false` if there are none
+    pub async fn start_tag(self: &Arc<Self>) -> bool {
+        let time_packet = {
+            let mut tag = self.tag.write().await;
+            if !tag.has_seekers() {
+                return false;
+            }
 
-        let mut peers = self.peers.write().await;
-        let peer = peers.get_mut(&player.id)?;
+            tag.start();
+            tag.time_packet()
+        };
 
-        for id in diff {
-            player.moons.insert(id);
+        self.send_tag_state().await;
+        self.send_tag_time(time_packet);
 
-            let packet = MoonPacket {
-                id,
-                is_grand: false,
-            };
+        true
+    }
 
-            peer.send_nil_uuid(packet).await;
+    /// Stop the current round, returns `false` if no round was running
+    pub async fn stop_tag(self: &Arc<Self>) -> bool {
+        self.finish_tag_round().await
+    }
+
+    pub async fn set_tag_seekers(self: &Arc<Self>, seekers: HashSet<Uuid>) {
+        let is_running = {
+            let mut tag = self.tag.write().await;
+            tag.set_seekers(seekers);
+
+            tag.is_running()
+        };
+
+        if is_running {
+            self.send_tag_state().await;
+            self.check_tag_round().await;
         }
+    }
 
-        Ok(())
+    /// Set the round time, returns `true` if a round is running and keeps its timer
+    pub async fn set_tag_time(&self, round_time: Duration) -> bool {
+        let mut tag = self.tag.write().await;
+        tag.set_time(round_time);
+
+        tag.is_running()
+    }
+
+    /// End the round once every connected player has been found
+    async fn check_tag_round(&self) {
+        let all_found = {
+            let tag = self.tag.read().await;
+            if !tag.is_running() {
+                return;
+            }
+
+            tag.all_found(self.peers.keys())
+        };
+
+        if all_found {
+            self.end_tag_round("All hiders found, seekers win!").await;
+        }
+    }
+
+    async fn end_tag_round(&self, reason: &str) {
+        if self.finish_tag_round().await {
+            info!("Hide and seek round over: {reason}");
+        }
+    }
+
+    /// Stop the round and send everyone its final state, returns `false` if no round was running
+    async fn finish_tag_round(&self) -> bool {
+        let time_packet = {
+            let mut tag = self.tag.write().await;
+            if !tag.stop() {
+                return false;
+            }
+
+            tag.time_packet()
+        };
+
+        self.send_tag_state().await;
+        self.send_tag_time(time_packet);
+
+        true
+    }
+
+    /// Send each player's seeker state to everyone
+    async fn send_tag_state(&self) {
+        let tag = self.tag.read().await;
+        for id in self.peers.keys() {
+            let packet = tag.state_packet(&id).into_packet(id);
+            self.peers.send_all(packet);
+        }
+    }
+
+    /// Send the round timer to each player
+    fn send_tag_time(&self, packet: TagPacket) {
+        for id in self.peers.keys() {
+            self.peers.send(&id, packet.into_packet(id));
+        }
     }
     // endregion
 }
+
+/// Remove the password from a connect packet
+///
+/// Clients that support it send the password after the connect packet, see [`PASSWORD_TAG`],
+/// vanilla clients can only add it to the end of their nickname, eg: `name#password`
+fn take_password(data: &mut ConnectPacket, trailing: &mut Bytes) -> Result<Option<String>> {
+    if let Some(rest) = trailing.strip_prefix(PASSWORD_TAG) {
+        let (&len, rest) = rest
+            .split_first()
+            .ok_or_else(|| eyre!("missing password length"))?;
+
+        let password = rest
+            .get(..usize::from(len))
+            .ok_or_else(|| eyre!("password is longer than the packet"))?;
+        let password = std::str::from_utf8(password)?.to_owned();
+
+        trailing.advance(PASSWORD_TAG.len() + 1 + usize::from(len));
+        return Ok(Some(password));
+    }
+
+    let nickname = data.nickname.try_to_string()?;
+    match nickname.rsplit_once('#') {
+        Some((name, password)) => {
+            data.nickname = name.parse()?;
+            Ok(Some(password.to_owned()))
+        }
+
+        None => Ok(None),
+    }
+}
+
+/// Add a `_number` suffix to a nickname, cut to fit in [`NICKNAME_LEN`] bytes
+fn suffixed_name(name: &str, number: u32) -> String {
+    let suffix = format!("_{number}");
+
+    // Cut the nickname to fit the suffix, without splitting characters
+    let mut len = name.len().min(NICKNAME_LEN - suffix.len());
+    while !name.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    format!("{}{suffix}", &name[..len])
+}
+
+/// Whether a persistence file has to be written after the config changed,
+/// and the old file to remove if it was moved
+fn persist_change(old: (bool, &PathBuf), new: (bool, &PathBuf)) -> (bool, Option<PathBuf>) {
+    let ((old_persist, old_file), (new_persist, new_file)) = (old, new);
+    if !new_persist {
+        return (false, None);
+    }
+
+    let moved = old_persist && old_file != new_file;
+    (moved || !old_persist, moved.then(|| old_file.clone()))
+}
+
+/// Remove a persistence file after its contents were saved to a new path
+async fn remove_moved_file(old_file: Option<PathBuf>) -> Result<()> {
+    if let Some(old_file) = old_file {
+        if old_file.exists() {
+            tokio::fs::remove_file(&old_file).await?;
+            info!("Moved {} to its new path", old_file.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::FixedString;
+
+    fn connect_packet(nickname: &str) -> ConnectPacket {
+        ConnectPacket {
+            connection_type: ConnectionType::Init,
+            max_players: 8,
+            nickname: nickname.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_suffixed_name() {
+        assert_eq!(suffixed_name("Mario", 2), "Mario_2");
+
+        let name = "a".repeat(NICKNAME_LEN);
+        let suffixed = suffixed_name(&name, 12);
+        assert_eq!(suffixed.len(), NICKNAME_LEN);
+        assert!(suffixed.ends_with("a_12"));
+
+        // Multi-byte characters are never split
+        let name = "é".repeat(NICKNAME_LEN / 2);
+        let suffixed = suffixed_name(&name, 2);
+        assert!(suffixed.len() <= NICKNAME_LEN);
+        assert_eq!(suffixed, format!("{}_2", "é".repeat(NICKNAME_LEN / 2 - 1)));
+        assert!(suffixed.parse::<FixedString<NICKNAME_LEN>>().is_ok());
+    }
+
+    #[test]
+    fn test_take_password_trailing() {
+        let mut data = connect_packet("Mario");
+        let mut trailing = Bytes::from_static(b"PASS\x06secret\x01\x02");
+
+        let password = take_password(&mut data, &mut trailing).unwrap();
+        assert_eq!(password.as_deref(), Some("secret"));
+        assert_eq!(&trailing[..], b"\x01\x02");
+        assert_eq!(data.nickname.try_as_str().unwrap(), "Mario");
+    }
+
+    #[test]
+    fn test_take_password_keeps_other_trailing_bytes() {
+        let mut data = connect_packet("Mario");
+        let mut trailing = Bytes::from_static(b"secret\0");
+
+        let password = take_password(&mut data, &mut trailing).unwrap();
+        assert_eq!(password, None);
+        assert_eq!(&trailing[..], b"secret\0");
+    }
+
+    #[test]
+    fn test_take_password_nickname() {
+        let mut data = connect_packet("Mario#secret");
+        let mut trailing = Bytes::new();
+
+        let password = take_password(&mut data, &mut trailing).unwrap();
+        assert_eq!(password.as_deref(), Some("secret"));
+        assert_eq!(data.nickname.try_as_str().unwrap(), "Mario");
+    }
+
+    #[test]
+    fn test_take_password_truncated() {
+        let mut data = connect_packet("Mario");
+        let mut trailing = Bytes::from_static(b"PASS\x10short");
+
+        assert!(take_password(&mut data, &mut trailing).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use tokio::time::Duration;
+use uuid::Uuid;
+
+use crate::packet::TagPacket;
+
+/// Server-owned state of a hide and seek round
+#[derive(Debug)]
+pub struct TagGame {
+    running: bool,
+    seekers: HashSet<Uuid>,
+
+    round_time: Duration,
+    remaining: Duration,
+}
+
+impl Default for TagGame {
+    #[inline]
+    fn default() -> Self {
+        let round_time = Duration::from_secs(5 * 60);
+
+        Self {
+            running: false,
+            seekers: HashSet::new(),
+
+            round_time,
+            remaining: round_time,
+        }
+    }
+}
+
+impl TagGame {
+    #[inline]
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    #[inline]
+    pub fn is_seeker(&self, id: &Uuid) -> bool {
+        self.seekers.contains(id)
+    }
+
+    #[inline]
+    pub fn has_seekers(&self) -> bool {
+        !self.seekers.is_empty()
+    }
+
+    pub fn start(&mut self) {
+        self.running = true;
+        self.remaining = self.round_time;
+    }
+
+    /// Returns `true` if a round was running
+    pub fn stop(&mut self) -> bool {
+        let was_running = self.running;
+        self.running = false;
+
+        was_running
+    }
+
+    #[inline]
+    pub fn set_seekers(&mut self, seekers: HashSet<Uuid>) {
+        self.seekers = seekers;
+    }
+
+    /// Update a single player's state, eg: when a hider is found
+    #[inline]
+    pub fn set_seeker(&mut self, id: Uuid, is_it: bool) {
+        if is_it {
+            self.seekers.insert(id);
+        } else {
+            self.seekers.remove(&id);
+        }
+    }
+
+    /// Set the round time, a running round keeps its timer and the new time
+    /// is used from the next round
+    pub fn set_time(&mut self, round_time: Duration) {
+        self.round_time = round_time;
+        if !self.is_running() {
+            self.remaining = round_time;
+        }
+    }
+
+    /// Advance the timer, returns `true` once time has run out
+    pub fn tick(&mut self, elapsed: Duration) -> bool {
+        self.remaining = self.remaining.saturating_sub(elapsed);
+        self.remaining.is_zero()
+    }
+
+    /// Returns `true` if every given player is a seeker
+    pub fn all_found(&self, players: impl IntoIterator<Item = Uuid>) -> bool {
+        players.into_iter().all(|id| self.seekers.contains(&id))
+    }
+
+    #[inline]
+    pub fn time_packet(&self) -> TagPacket {
+        let seconds = self.remaining.as_secs();
+        let minutes = u16::try_from(seconds / 60).unwrap_or(u16::MAX);
+        let seconds = (seconds % 60) as u8;
+
+        TagPacket::time(minutes, seconds)
+    }
+
+    #[inline]
+    pub fn state_packet(&self, id: &Uuid) -> TagPacket {
+        TagPacket::state(self.is_seeker(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(seekers: &[Uuid]) -> TagGame {
+        let mut game = TagGame::default();
+        game.set_seekers(seekers.iter().copied().collect());
+        game
+    }
+
+    #[test]
+    fn test_round_timer() {
+        let mut game = game(&[Uuid::new_v4()]);
+        game.set_time(Duration::from_secs(90));
+        game.start();
+        assert!(game.is_running());
+        assert_eq!(game.time_packet(), TagPacket::time(1, 30));
+
+        assert!(!game.tick(Duration::from_secs(89)));
+        assert_eq!(game.time_packet(), TagPacket::time(0, 1));
+        assert!(game.tick(Duration::from_secs(1)));
+        assert!(game.tick(Duration::from_secs(1)));
+        assert_eq!(game.time_packet(), TagPacket::time(0, 0));
+
+        assert!(game.stop());
+        assert!(!game.stop());
+        assert!(!game.is_running());
+    }
+
+    #[test]
+    fn test_start_resets_timer() {
+        let mut game = game(&[Uuid::new_v4()]);
+        game.set_time(Duration::from_secs(60));
+        game.start();
+        game.tick(Duration::from_secs(30));
+        game.stop();
+
+        game.start();
+        assert_eq!(game.time_packet(), TagPacket::time(1, 0));
+    }
+
+    #[test]
+    fn test_set_time_next_round() {
+        let mut game = game(&[Uuid::new_v4()]);
+        game.set_time(Duration::from_secs(60));
+        game.start();
+        game.tick(Duration::from_secs(10));
+
+        game.set_time(Duration::from_secs(120));
+        assert_eq!(game.time_packet(), TagPacket::time(0, 50));
+
+        game.stop();
+        game.start();
+        assert_eq!(game.time_packet(), TagPacket::time(2, 0));
+    }
+
+    #[test]
+    fn test_all_found() {
+        let (seeker, hider) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut game = game(&[seeker]);
+        assert!(game.has_seekers());
+        assert!(!game.all_found([seeker, hider]));
+        assert_eq!(game.state_packet(&hider), TagPacket::state(false));
+
+        game.set_seeker(hider, true);
+        assert!(game.all_found([seeker, hider]));
+        assert_eq!(game.state_packet(&hider), TagPacket::state(true));
+
+        game.set_seeker(seeker, false);
+        assert!(!game.is_seeker(&seeker));
+        assert!(!game.all_found([seeker, hider]));
+    }
+}